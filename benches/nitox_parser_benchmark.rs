@@ -25,6 +25,7 @@ fn benchmark_parser(c: &mut Criterion) {
                 subject: String::new(),
                 payload: bytes::Bytes::new(),
                 reply_to: None,
+                headers: None,
             }.into_vec()
         })
     });
@@ -90,6 +91,7 @@ fn benchmark_parser(c: &mut Criterion) {
                 sid: String::new(),
                 reply_to: None,
                 payload: bytes::Bytes::new(),
+                headers: None,
             }.into_vec()
         })
     });
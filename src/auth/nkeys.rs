@@ -0,0 +1,257 @@
+//! NKeys are Ed25519 keys encoded in base32 with a CRC16 checksum, the first letter of their string form telling
+//! the kind of entity they identify (`U` for users, `N` for servers...). Seeds start with `S` followed by the
+//! letter of their kind, as in `SU...` for the seed of a user.
+//!
+//! The server authenticates NKey users by having them sign the `nonce` of its INFO message.
+
+use data_encoding::{BASE32_NOPAD, BASE64URL_NOPAD};
+use ed25519_dalek::{ExpandedSecretKey, PublicKey, SecretKey, Signature, Verifier};
+use std::{
+    convert::TryFrom,
+    fmt, ptr,
+    sync::atomic::{self, Ordering},
+};
+
+use error::NatsError;
+
+const PREFIX_BYTE_SEED: u8 = 18 << 3;
+
+/// Kind of entity identified by an NKey
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyPairType {
+    Server,
+    Cluster,
+    Operator,
+    Account,
+    User,
+}
+
+impl KeyPairType {
+    fn prefix_byte(self) -> u8 {
+        match self {
+            KeyPairType::Server => 13 << 3,
+            KeyPairType::Cluster => 2 << 3,
+            KeyPairType::Operator => 14 << 3,
+            KeyPairType::Account => 0,
+            KeyPairType::User => 20 << 3,
+        }
+    }
+
+    fn from_prefix_byte(prefix_byte: u8) -> Option<Self> {
+        [
+            KeyPairType::Server,
+            KeyPairType::Cluster,
+            KeyPairType::Operator,
+            KeyPairType::Account,
+            KeyPairType::User,
+        ]
+            .iter()
+            .cloned()
+            .find(|kind| kind.prefix_byte() == prefix_byte)
+    }
+}
+
+/// Raw Ed25519 seed, wiped from memory once dropped
+#[derive(Clone, PartialEq)]
+struct Seed([u8; 32]);
+
+impl Drop for Seed {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+/// Zeroes sensitive bytes. Volatile writes keep the compiler from optimizing the wipe away as dead stores
+fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+/// Ed25519 key pair decoded from an NKey seed, or public key only when decoded from an NKey public key
+#[derive(Clone, PartialEq)]
+pub struct KeyPair {
+    kind: KeyPairType,
+    public: PublicKey,
+    seed: Option<Seed>,
+}
+
+impl KeyPair {
+    /// Decodes a seed such as `SUAM...`, giving a key pair able to sign
+    pub fn from_seed(seed: &str) -> Result<Self, NatsError> {
+        let mut raw = decode(seed)?;
+        let keypair = KeyPair::from_raw_seed(&raw);
+        wipe(&mut raw);
+        keypair
+    }
+
+    fn from_raw_seed(raw: &[u8]) -> Result<Self, NatsError> {
+        if raw.len() != 34 || raw[0] & 248 != PREFIX_BYTE_SEED {
+            return Err(NatsError::NKeyError("not a seed".into()));
+        }
+
+        let kind = KeyPairType::from_prefix_byte(((raw[0] & 7) << 5) | ((raw[1] & 248) >> 3))
+            .ok_or_else(|| NatsError::NKeyError("unknown key type".into()))?;
+        let secret = SecretKey::from_bytes(&raw[2..]).map_err(|e| NatsError::NKeyError(e.to_string()))?;
+        let mut seed = Seed([0; 32]);
+        seed.0.copy_from_slice(&raw[2..]);
+
+        Ok(KeyPair {
+            kind,
+            public: PublicKey::from(&secret),
+            seed: Some(seed),
+        })
+    }
+
+    /// Decodes a public key such as `UDXU...`, giving a key pair only able to verify signatures
+    pub fn from_public_key(public_key: &str) -> Result<Self, NatsError> {
+        let raw = decode(public_key)?;
+        if raw.len() != 33 {
+            return Err(NatsError::NKeyError("not a public key".into()));
+        }
+
+        let kind =
+            KeyPairType::from_prefix_byte(raw[0]).ok_or_else(|| NatsError::NKeyError("unknown key type".into()))?;
+        let public = PublicKey::from_bytes(&raw[1..]).map_err(|e| NatsError::NKeyError(e.to_string()))?;
+
+        Ok(KeyPair {
+            kind,
+            public,
+            seed: None,
+        })
+    }
+
+    pub fn key_type(&self) -> KeyPairType {
+        self.kind
+    }
+
+    /// Encoded public key, which identifies the key pair to the server
+    pub fn public_key(&self) -> String {
+        let mut raw = vec![self.kind.prefix_byte()];
+        raw.extend_from_slice(self.public.as_bytes());
+        encode(raw)
+    }
+
+    /// Signs `data`; Fails if the key pair has no seed
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, NatsError> {
+        let seed = self
+            .seed
+            .as_ref()
+            .ok_or_else(|| NatsError::NKeyError("cannot sign without a seed".into()))?;
+        let secret = SecretKey::from_bytes(&seed.0).map_err(|e| NatsError::NKeyError(e.to_string()))?;
+
+        Ok(ExpandedSecretKey::from(&secret).sign(data, &self.public).to_bytes().to_vec())
+    }
+
+    /// Verifies that `sig` is the signature of `data` by this key pair
+    pub fn verify(&self, data: &[u8], sig: &[u8]) -> Result<(), NatsError> {
+        let sig = Signature::try_from(sig).map_err(|e| NatsError::NKeyError(e.to_string()))?;
+        self.public
+            .verify(data, &sig)
+            .map_err(|e| NatsError::NKeyError(e.to_string()))
+    }
+
+    /// Signs the nonce sent by the server, encoding the signature the way CONNECT carries it
+    pub(crate) fn sign_nonce(&self, nonce: &str) -> Result<String, NatsError> {
+        Ok(BASE64URL_NOPAD.encode(&self.sign(nonce.as_bytes())?))
+    }
+}
+
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Keeps the seed out of the logs
+        f.debug_struct("KeyPair")
+            .field("kind", &self.kind)
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+/// CRC16 (XMODEM) checksum appended to encoded keys
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+fn encode(mut raw: Vec<u8>) -> String {
+    let crc = crc16(&raw);
+    raw.push(crc as u8);
+    raw.push((crc >> 8) as u8);
+    BASE32_NOPAD.encode(&raw)
+}
+
+/// Decodes a key, checking and stripping its checksum
+fn decode(key: &str) -> Result<Vec<u8>, NatsError> {
+    let mut raw = BASE32_NOPAD
+        .decode(key.trim().as_bytes())
+        .map_err(|e| NatsError::NKeyError(e.to_string()))?;
+    if raw.len() < 2 {
+        return Err(NatsError::NKeyError("key is too short".into()));
+    }
+
+    let crc_pos = raw.len() - 2;
+    let crc = u16::from(raw[crc_pos]) | (u16::from(raw[crc_pos + 1]) << 8);
+    raw.truncate(crc_pos);
+    if crc16(&raw) != crc {
+        return Err(NatsError::NKeyError("invalid checksum".into()));
+    }
+
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyPair, KeyPairType};
+    use data_encoding::BASE64URL_NOPAD;
+
+    const SEED: &str = "SUAAOBYHA4DQOBYHA4DQOBYHA4DQOBYHA4DQOBYHA4DQOBYHA4DQOBZ5FE";
+    const PUBLIC_KEY: &str = "UDVEU3DD4KOFECV66VIHWEZOYX4ZKR3WV27L464SIIPOU2IUI3JCYSJF";
+
+    #[test]
+    fn it_decodes_seeds() {
+        let keypair = KeyPair::from_seed(SEED).unwrap();
+        assert_eq!(keypair.key_type(), KeyPairType::User);
+        assert_eq!(keypair.public_key(), PUBLIC_KEY);
+    }
+
+    #[test]
+    fn it_signs_nonces() {
+        let keypair = KeyPair::from_seed(SEED).unwrap();
+        let sig = keypair.sign_nonce("nonce123").unwrap();
+        assert_eq!(
+            sig,
+            "3JCGaEC4dgyASLVHuhe57o_NHlEthkCtQtR2MBGQ3yQj0xJDlN3goZDYzWbB0icv8dp9JyrtrK68vj49glaVCw"
+        );
+
+        let public_keypair = KeyPair::from_public_key(PUBLIC_KEY).unwrap();
+        let sig = BASE64URL_NOPAD.decode(sig.as_bytes()).unwrap();
+        assert!(public_keypair.verify(b"nonce123", &sig).is_ok());
+        assert!(public_keypair.verify(b"nonce124", &sig).is_err());
+        assert!(public_keypair.sign(b"nonce123").is_err());
+    }
+
+    #[test]
+    fn it_keeps_seeds_out_of_debug() {
+        let keypair = KeyPair::from_seed(SEED).unwrap();
+        let debug = format!("{:?}", keypair);
+        assert!(debug.contains(PUBLIC_KEY));
+        assert!(!debug.contains("seed"));
+    }
+
+    #[test]
+    fn it_rejects_invalid_keys() {
+        let tampered = SEED.replace("SUAAO", "SUAAP");
+        assert!(KeyPair::from_seed(&tampered).is_err());
+        assert!(KeyPair::from_seed(PUBLIC_KEY).is_err());
+        assert!(KeyPair::from_public_key(SEED).is_err());
+        assert!(KeyPair::from_seed("not base32!").is_err());
+    }
+}
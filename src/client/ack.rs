@@ -0,0 +1,177 @@
+use futures::{
+    future::{self, Either},
+    prelude::*,
+    sync::oneshot,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use error::NatsError;
+
+type AckSender = oneshot::Sender<Result<(), NatsError>>;
+
+/// Commands sent in verbose mode that are waiting for the server to acknowledge them with `+OK` or to reject them
+/// with `-ERR`. The server answering commands in order, each acknowledgement goes to the oldest waiter
+#[derive(Debug, Default)]
+pub(crate) struct AckWaiters {
+    /// Senders of the commands waiting for their acknowledgement. Commands nobody waits for have no sender
+    waiters: Mutex<VecDeque<Option<AckSender>>>,
+    /// Number of these commands still queued for the sink, which haven't been written nor buffered yet
+    unsent: AtomicUsize,
+}
+
+impl AckWaiters {
+    pub fn new() -> Self {
+        AckWaiters::default()
+    }
+
+    /// Queues a command through `send` and returns a future resolving once the server acknowledged it. Waiters are
+    /// registered in the order their commands are queued, so that each one gets its own acknowledgement
+    pub fn wait_for_ack<F>(&self, send: F) -> impl Future<Item = (), Error = NatsError>
+    where
+        F: FnOnce() -> Result<(), NatsError>,
+    {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut waiters = self.waiters.lock();
+            if let Err(e) = self.queue(send) {
+                return Either::A(future::err(e));
+            }
+
+            waiters.push_back(Some(tx));
+        }
+
+        Either::B(rx.then(|res| match res {
+            Ok(ack_res) => ack_res,
+            Err(_) => Err(NatsError::InnerBrokenChain),
+        }))
+    }
+
+    /// Queues a command through `send` whose acknowledgement nobody waits for, keeping track of it all the same
+    pub fn expect_ack<F>(&self, send: F) -> Result<(), NatsError>
+    where
+        F: FnOnce() -> Result<(), NatsError>,
+    {
+        let mut waiters = self.waiters.lock();
+        self.queue(send)?;
+        waiters.push_back(None);
+        Ok(())
+    }
+
+    /// Counts the command as unsent until the sink picks it up. It is counted beforehand, as the sink may pick it up
+    /// right away
+    fn queue<F>(&self, send: F) -> Result<(), NatsError>
+    where
+        F: FnOnce() -> Result<(), NatsError>,
+    {
+        self.unsent.fetch_add(1, Ordering::SeqCst);
+        let res = send();
+        if res.is_err() {
+            self.unsent.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        res
+    }
+
+    /// Tells that the sink picked up one of the commands, which it either writes or buffers
+    pub fn mark_sent(&self) {
+        self.unsent.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Prepares for a new connection, on which the `replayed` commands restoring the session are sent first. They
+    /// are followed by the last `buffered` commands, which were buffered while reconnecting, and by the unsent ones,
+    /// whose waiters are kept. The other waiters fail, as their commands got lost along with the previous connection
+    pub fn restore<F: Fn() -> NatsError>(&self, replayed: usize, buffered: usize, err: F) {
+        let mut waiters = self.waiters.lock();
+        let kept = buffered + self.unsent.load(Ordering::SeqCst);
+        let lost = waiters.len().saturating_sub(kept);
+        for tx in waiters.drain(..lost).flatten() {
+            let _ = tx.send(Err(err()));
+        }
+
+        for _ in 0..replayed {
+            waiters.push_front(None);
+        }
+    }
+
+    /// Hands an acknowledgement, or the error rejecting the command, to the oldest waiter; Returns `false` if
+    /// nobody was waiting for it
+    pub fn resolve(&self, res: Result<(), NatsError>) -> bool {
+        match self.waiters.lock().pop_front() {
+            Some(tx) => {
+                if let Some(tx) = tx {
+                    let _ = tx.send(res);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Fails every waiter, as their acknowledgements won't ever come
+    pub fn fail_all<F: Fn() -> NatsError>(&self, err: F) {
+        for tx in self.waiters.lock().drain(..).flatten() {
+            let _ = tx.send(Err(err()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AckWaiters;
+    use error::NatsError;
+    use futures::prelude::*;
+
+    #[test]
+    fn it_resolves_acks_in_order() {
+        let acks = AckWaiters::new();
+        let first = acks.wait_for_ack(|| Ok(()));
+        assert!(acks.expect_ack(|| Ok(())).is_ok());
+        let third = acks.wait_for_ack(|| Ok(()));
+        let fourth = acks.wait_for_ack(|| Ok(()));
+
+        assert!(acks.resolve(Ok(())));
+        assert!(acks.resolve(Err(NatsError::InnerBrokenChain)));
+        assert!(acks.resolve(Err(NatsError::AuthorizationViolation)));
+        acks.fail_all(|| NatsError::ServerDisconnected(None));
+        assert!(first.wait().is_ok());
+        match third.wait() {
+            Err(NatsError::AuthorizationViolation) => {}
+            res => panic!("Expected the third command to be rejected, got {:?}", res),
+        }
+        assert!(fourth.wait().is_err());
+        assert!(!acks.resolve(Ok(())));
+    }
+
+    #[test]
+    fn it_keeps_buffered_commands_across_reconnections() {
+        let acks = AckWaiters::new();
+        let lost = acks.wait_for_ack(|| Ok(()));
+        let buffered = acks.wait_for_ack(|| Ok(()));
+        let unsent = acks.wait_for_ack(|| Ok(()));
+        acks.mark_sent();
+        acks.mark_sent();
+
+        // The replayed commands are acknowledged first
+        acks.restore(2, 1, || NatsError::ServerDisconnected(None));
+        assert!(lost.wait().is_err());
+        assert!(acks.resolve(Err(NatsError::InnerBrokenChain)));
+        assert!(acks.resolve(Ok(())));
+        assert!(acks.resolve(Ok(())));
+        assert!(acks.resolve(Ok(())));
+        assert!(buffered.wait().is_ok());
+        assert!(unsent.wait().is_ok());
+        assert!(!acks.resolve(Ok(())));
+    }
+
+    #[test]
+    fn it_forgets_unsent_commands() {
+        let acks = AckWaiters::new();
+        assert!(acks.wait_for_ack(|| Err(NatsError::InnerBrokenChain)).wait().is_err());
+        assert!(acks.expect_ack(|| Err(NatsError::InnerBrokenChain)).is_err());
+        assert!(!acks.resolve(Ok(())));
+    }
+}
@@ -0,0 +1,268 @@
+use futures::{prelude::*, task::AtomicTask};
+use parking_lot::Mutex;
+use std::{collections::VecDeque, sync::Arc};
+
+use error::NatsError;
+use protocol::commands::Message;
+
+use super::{SlowConsumerPolicy, SubscribeOptions};
+
+#[derive(Debug, Default)]
+struct PendingMessagesInner {
+    /// Messages waiting to be consumed, possibly followed by the error ending the stream
+    messages: VecDeque<Result<Message, NatsError>>,
+    /// Size of the payloads of the messages, in bytes
+    bytes: usize,
+    /// Number of messages dropped because of the limits
+    dropped: u64,
+    /// Set while messages are being dropped, so that the slow consumer is only reported once until it caught up
+    slow: bool,
+    /// Set once no more messages are to be queued
+    closed: bool,
+}
+
+/// Messages of a subscription waiting to be consumed, fed by the multiplexer. Messages coming while the pending
+/// limits of the subscription are reached are handled according to its `SlowConsumerPolicy`
+#[derive(Debug)]
+pub(crate) struct PendingMessages {
+    inner: Mutex<PendingMessagesInner>,
+    /// Task consuming the messages, woken up when a message comes or when the stream ends
+    task: AtomicTask,
+    opts: SubscribeOptions,
+}
+
+impl PendingMessages {
+    pub fn new(opts: SubscribeOptions) -> Self {
+        PendingMessages {
+            inner: Mutex::new(PendingMessagesInner::default()),
+            task: AtomicTask::new(),
+            opts,
+        }
+    }
+
+    /// Whether a message with a payload of `len` bytes fits within the limits
+    fn has_room_for(&self, inner: &PendingMessagesInner, len: usize) -> bool {
+        let msgs_ok = self
+            .opts
+            .pending_msgs_limit
+            .map_or(true, |limit| inner.messages.len() < limit);
+        let bytes_ok = self
+            .opts
+            .pending_bytes_limit
+            .map_or(true, |limit| inner.bytes + len <= limit);
+        msgs_ok && bytes_ok
+    }
+
+    /// Queues a message if the limits allow it, applying the slow consumer policy otherwise. Returns the number of
+    /// messages dropped so far when the subscription just became a slow consumer
+    pub fn push(&self, msg: Message) -> Option<u64> {
+        let became_slow = {
+            let mut inner = self.inner.lock();
+            if inner.closed {
+                return None;
+            }
+
+            let len = msg.payload.len();
+            if self.has_room_for(&inner, len) {
+                inner.bytes += len;
+                inner.messages.push_back(Ok(msg));
+                inner.slow = false;
+                None
+            } else {
+                // No need to make room for a message too big for the limits on its own
+                let too_big = self.opts.pending_bytes_limit.map_or(false, |limit| len > limit);
+                match self.opts.slow_consumer_policy {
+                    SlowConsumerPolicy::DropNew => inner.dropped += 1,
+                    SlowConsumerPolicy::DropOldest if too_big => inner.dropped += 1,
+                    SlowConsumerPolicy::DropOldest => {
+                        while !self.has_room_for(&inner, len) {
+                            match inner.messages.pop_front() {
+                                Some(Ok(old)) => {
+                                    inner.bytes -= old.payload.len();
+                                    inner.dropped += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+
+                        inner.bytes += len;
+                        inner.messages.push_back(Ok(msg));
+                    }
+                    SlowConsumerPolicy::Error => {
+                        inner.dropped += 1;
+                        inner.messages.push_back(Err(NatsError::SlowConsumer));
+                        inner.closed = true;
+                    }
+                }
+
+                if inner.slow {
+                    None
+                } else {
+                    inner.slow = true;
+                    Some(inner.dropped)
+                }
+            }
+        };
+
+        self.task.notify();
+        became_slow
+    }
+
+    /// Ends the stream with `err` once the messages already queued are consumed
+    pub fn fail(&self, err: NatsError) {
+        {
+            let mut inner = self.inner.lock();
+            if inner.closed {
+                return;
+            }
+
+            inner.messages.push_back(Err(err));
+            inner.closed = true;
+        }
+
+        self.task.notify();
+    }
+
+    /// Ends the stream once the messages already queued are consumed
+    pub fn close(&self) {
+        self.inner.lock().closed = true;
+        self.task.notify();
+    }
+
+    /// Number of messages dropped because of the limits
+    pub fn dropped(&self) -> u64 {
+        self.inner.lock().dropped
+    }
+
+    fn poll_message(&self) -> Poll<Option<Message>, NatsError> {
+        let mut inner = self.inner.lock();
+        match inner.messages.pop_front() {
+            Some(Ok(msg)) => {
+                inner.bytes -= msg.payload.len();
+                Ok(Async::Ready(Some(msg)))
+            }
+            Some(Err(e)) => Err(e),
+            None if inner.closed => Ok(Async::Ready(None)),
+            None => {
+                // Registered under the lock so that the next push wakes us up
+                self.task.register();
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+/// Stream of the messages of a subscription, consuming its `PendingMessages`
+#[derive(Debug)]
+pub(crate) struct PendingMessagesStream(Arc<PendingMessages>);
+
+impl PendingMessagesStream {
+    pub fn new(pending: Arc<PendingMessages>) -> Self {
+        PendingMessagesStream(pending)
+    }
+
+    pub fn pending(&self) -> Arc<PendingMessages> {
+        Arc::clone(&self.0)
+    }
+}
+
+impl Stream for PendingMessagesStream {
+    type Error = NatsError;
+    type Item = Message;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.0.poll_message()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PendingMessages;
+    use client::{SlowConsumerPolicy, SubscribeOptions};
+    use error::NatsError;
+    use futures::{future, prelude::*};
+    use protocol::commands::Message;
+
+    fn message(payload: &'static str) -> Message {
+        Message::builder()
+            .subject("foo")
+            .sid("1")
+            .payload(payload)
+            .build()
+            .unwrap()
+    }
+
+    fn pending(policy: SlowConsumerPolicy) -> PendingMessages {
+        PendingMessages::new(
+            SubscribeOptions::builder()
+                .pending_msgs_limit(Some(2))
+                .pending_bytes_limit(Some(8))
+                .slow_consumer_policy(policy)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /// Consumes the queued messages until one isn't available, which is given along with their payloads
+    fn consume(pending: &PendingMessages) -> (Vec<String>, Poll<Option<Message>, NatsError>) {
+        let mut payloads = vec![];
+        // Polled from a task, which gets registered once there is nothing to consume
+        future::poll_fn(|| loop {
+            match pending.poll_message() {
+                Ok(Async::Ready(Some(msg))) => payloads.push(String::from_utf8(msg.payload.to_vec()).unwrap()),
+                res => return Ok::<_, ()>(Async::Ready(res)),
+            }
+        }).wait()
+        .map(|res| (payloads, res))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_drops_new_messages() {
+        let pending = pending(SlowConsumerPolicy::DropNew);
+        assert_eq!(pending.push(message("1")), None);
+        assert_eq!(pending.push(message("2")), None);
+        // Only reported once until there is room again
+        assert_eq!(pending.push(message("3")), Some(1));
+        assert_eq!(pending.push(message("4")), None);
+
+        assert_eq!(consume(&pending).0, vec!["1", "2"]);
+        assert_eq!(pending.push(message("5")), None);
+        assert_eq!(pending.push(message("6")), None);
+        assert_eq!(pending.push(message("7")), Some(3));
+        assert_eq!(pending.dropped(), 3);
+    }
+
+    #[test]
+    fn it_drops_old_messages() {
+        let pending = pending(SlowConsumerPolicy::DropOldest);
+        pending.push(message("1"));
+        pending.push(message("2"));
+        assert_eq!(pending.push(message("3")), Some(1));
+        // Makes room for the bytes as well, but not for a message that can't fit anyway
+        assert_eq!(pending.push(message("4567890")), None);
+        assert_eq!(pending.push(message("too big to fit")), None);
+
+        assert_eq!(consume(&pending).0, vec!["3", "4567890"]);
+        assert_eq!(pending.dropped(), 3);
+    }
+
+    #[test]
+    fn it_fails_slow_consumers() {
+        let pending = pending(SlowConsumerPolicy::Error);
+        pending.push(message("1"));
+        pending.push(message("2"));
+        assert_eq!(pending.push(message("3")), Some(1));
+        assert_eq!(pending.push(message("4")), None);
+
+        match consume(&pending) {
+            (ref payloads, Err(NatsError::SlowConsumer)) if *payloads == ["1", "2"] => {}
+            res => panic!("Expected a SlowConsumer error after 2 messages, got {:?}", res),
+        }
+        // The stream ends right after
+        match consume(&pending) {
+            (_, Ok(Async::Ready(None))) => {}
+            res => panic!("Expected the end of the stream, got {:?}", res),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use futures::{
+    future::{self, Either},
+    prelude::*,
+    sync::oneshot,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use error::NatsError;
+
+type PongSender = oneshot::Sender<Result<Duration, NatsError>>;
+
+#[derive(Debug, Default)]
+struct PongWaitersInner {
+    /// Senders of the PINGs waiting for their PONG, along with when they were sent. Keepalive PINGs have no sender
+    waiters: VecDeque<(Option<PongSender>, Instant)>,
+    /// Keepalive PINGs sent since the last PONG
+    keepalive_outstanding: usize,
+    /// Round-trip time of the last PING answered by the server
+    last_rtt: Option<Duration>,
+}
+
+/// PINGs sent by the client that are waiting for their PONG. The server answering PINGs in order, each PONG
+/// goes to the oldest waiter
+#[derive(Debug, Default)]
+pub(crate) struct PongWaiters {
+    inner: Mutex<PongWaitersInner>,
+}
+
+impl PongWaiters {
+    pub fn new() -> Self {
+        PongWaiters::default()
+    }
+
+    /// Queues a PING through `send` and returns a future resolving to its round-trip time once the server answered
+    /// it. Waiters are registered in the order their PINGs are queued, so that each one gets its own PONG
+    pub fn ping<F>(&self, send: F) -> impl Future<Item = Duration, Error = NatsError>
+    where
+        F: FnOnce() -> Result<(), NatsError>,
+    {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut inner = self.inner.lock();
+            if let Err(e) = send() {
+                return Either::A(future::err(e));
+            }
+
+            inner.waiters.push_back((Some(tx), Instant::now()));
+        }
+
+        Either::B(rx.then(|res| match res {
+            Ok(pong_res) => pong_res,
+            Err(_) => Err(NatsError::InnerBrokenChain),
+        }))
+    }
+
+    /// Queues a keepalive PING through `send`, unless `max_outstanding` of them are already left unanswered,
+    /// meaning that the connection is stale; Returns whether the PING was sent
+    pub fn ping_keepalive<F>(&self, max_outstanding: usize, send: F) -> Result<bool, NatsError>
+    where
+        F: FnOnce() -> Result<(), NatsError>,
+    {
+        let mut inner = self.inner.lock();
+        if inner.keepalive_outstanding >= max_outstanding {
+            return Ok(false);
+        }
+
+        send()?;
+        inner.keepalive_outstanding += 1;
+        inner.waiters.push_back((None, Instant::now()));
+        Ok(true)
+    }
+
+    /// Hands a PONG to the oldest waiter; Returns `false` if nobody was waiting for it
+    pub fn resolve(&self) -> bool {
+        let mut inner = self.inner.lock();
+        // Any PONG proves that the connection is alive
+        inner.keepalive_outstanding = 0;
+        match inner.waiters.pop_front() {
+            Some((tx, sent_at)) => {
+                let rtt = sent_at.elapsed();
+                inner.last_rtt = Some(rtt);
+                if let Some(tx) = tx {
+                    let _ = tx.send(Ok(rtt));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Fails every waiter, as their PONGs won't ever come
+    pub fn fail_all<F: Fn() -> NatsError>(&self, err: F) {
+        let mut inner = self.inner.lock();
+        inner.keepalive_outstanding = 0;
+        for (tx, _) in inner.waiters.drain(..) {
+            if let Some(tx) = tx {
+                let _ = tx.send(Err(err()));
+            }
+        }
+    }
+
+    /// Returns the round-trip time of the last PING answered by the server
+    pub fn last_rtt(&self) -> Option<Duration> {
+        self.inner.lock().last_rtt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PongWaiters;
+    use error::NatsError;
+    use futures::prelude::*;
+
+    #[test]
+    fn it_detects_stale_connections() {
+        let pongs = PongWaiters::new();
+        assert!(pongs.ping_keepalive(2, || Ok(())).unwrap());
+        assert!(pongs.ping_keepalive(2, || Ok(())).unwrap());
+        assert!(!pongs.ping_keepalive(2, || Ok(())).unwrap());
+
+        assert!(pongs.resolve());
+        assert!(pongs.last_rtt().is_some());
+        assert!(pongs.ping_keepalive(2, || Ok(())).unwrap());
+    }
+
+    #[test]
+    fn it_resolves_pongs_in_order() {
+        let pongs = PongWaiters::new();
+        assert!(pongs.ping_keepalive(2, || Ok(())).unwrap());
+        let first = pongs.ping(|| Ok(()));
+        let second = pongs.ping(|| Ok(()));
+
+        assert!(pongs.resolve());
+        assert!(pongs.resolve());
+        pongs.fail_all(|| NatsError::ServerDisconnected(None));
+        assert!(first.wait().is_ok());
+        assert!(second.wait().is_err());
+        assert!(!pongs.resolve());
+    }
+
+    #[test]
+    fn it_forgets_unsent_pings() {
+        let pongs = PongWaiters::new();
+        assert!(pongs.ping(|| Err(NatsError::InnerBrokenChain)).wait().is_err());
+        assert!(!pongs.resolve());
+    }
+}
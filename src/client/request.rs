@@ -0,0 +1,248 @@
+use futures::{
+    future::{self, Either},
+    prelude::*,
+    sync::oneshot,
+};
+use parking_lot::Mutex;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio_executor;
+use tokio_timer::Timeout;
+
+use super::{connection_lost_error, NatsClientMultiplexer, NatsClientSender, SubscribeOptions};
+use error::NatsError;
+use protocol::{
+    commands::{Message, SubCommand},
+    HeaderMap, Op,
+};
+
+/// Strategy used by `NatsClient` to receive the replies of its requests
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestMode {
+    /// All requests share a single wildcard subscription on `_INBOX.<nuid>.*`, replies being routed to their
+    /// request by the last token of their subject. Only a PUB is sent per request.
+    Muxed,
+    /// Each request subscribes to its own inbox, which costs a SUB, an UNSUB and a PUB per request
+    PerRequestSubscription,
+}
+
+impl Default for RequestMode {
+    fn default() -> Self {
+        RequestMode::Muxed
+    }
+}
+
+/// Options that can be given to `NatsClient::request_with_options`
+#[derive(Debug, Default, Clone, Builder)]
+#[builder(default, setter(into))]
+pub struct RequestOptions {
+    /// Delay after which the request fails with `NatsError::RequestTimeout`. The request waits forever if `None`
+    pub timeout: Option<Duration>,
+    /// Headers to send along with the request payload
+    pub headers: Option<HeaderMap>,
+}
+
+impl RequestOptions {
+    pub fn builder() -> RequestOptionsBuilder {
+        RequestOptionsBuilder::default()
+    }
+}
+
+/// Sender half of the channel a muxed request receives its reply on
+type ReplySender = oneshot::Sender<Result<Message, NatsError>>;
+
+/// Shared reply inbox for muxed requests. The wildcard subscription is only made when the first request is sent
+#[derive(Debug)]
+pub(crate) struct RequestMultiplexer {
+    /// Inbox prefix of the replies, in the `_INBOX.<nuid>.` format
+    prefix: String,
+    /// Subscription ID of the wildcard subscription, once made and as long as it lasts
+    sid: Arc<Mutex<Option<String>>>,
+    /// Requests waiting for their reply, by reply token
+    pending: Arc<Mutex<HashMap<String, ReplySender>>>,
+}
+
+impl RequestMultiplexer {
+    pub fn new() -> Self {
+        let nuid: String = thread_rng().sample_iter(&Alphanumeric).take(22).collect();
+
+        RequestMultiplexer {
+            prefix: format!("_INBOX.{}.", nuid),
+            sid: Arc::new(Mutex::new(None)),
+            pending: Arc::new(Mutex::new(HashMap::default())),
+        }
+    }
+
+    /// Generates a random reply token
+    fn generate_token() -> String {
+        thread_rng().sample_iter(&Alphanumeric).take(8).collect()
+    }
+
+    /// Reply subject of the request identified by `token`
+    pub fn reply_to(&self, token: &str) -> String {
+        format!("{}{}", self.prefix, token)
+    }
+
+    /// Registers a request waiting for its reply under a token of its own, subscribing to the shared inbox if
+    /// needed. Must be called before sending the request PUB so that the SUB command is sent first
+    pub fn register(&self, tx: &NatsClientSender, rx: &NatsClientMultiplexer) -> Result<PendingReply, NatsError> {
+        // Held until the request is registered, so that it cannot miss the end of the inbox subscription
+        let mut sid = self.sid.lock();
+        if sid.is_none() {
+            let sub_cmd = SubCommand {
+                subject: format!("{}*", self.prefix),
+                queue_group: None,
+                sid: SubCommand::generate_sid(),
+            };
+
+            let prefix = self.prefix.clone();
+            let pending = Arc::clone(&self.pending);
+            let pending_end = Arc::clone(&self.pending);
+            let sid_end = Arc::clone(&self.sid);
+            let routing_sid = sub_cmd.sid.clone();
+            let routing = rx.for_sid(sub_cmd.clone(), SubscribeOptions::unlimited()).for_each(move |msg| {
+                let reply_tx = if msg.subject.starts_with(&prefix) {
+                    pending.lock().remove(&msg.subject[prefix.len()..])
+                } else {
+                    None
+                };
+
+                match reply_tx {
+                    Some(reply_tx) => {
+                        let _ = reply_tx.send(Ok(msg));
+                    }
+                    None => debug!(target: "nitox", "Dropping reply without pending request {:?}", msg),
+                }
+
+                future::ok(())
+            });
+
+            // The subscription ends once the connection is lost for good, but also once drained or closed by the
+            // client. Pending requests fail right away then, and the next request subscribes again
+            let routing = routing.then(move |res| {
+                let mut sid = sid_end.lock();
+                if sid.as_ref() == Some(&routing_sid) {
+                    *sid = None;
+                }
+
+                for (_, reply_tx) in pending_end.lock().drain() {
+                    let err = match res {
+                        Ok(_) => NatsError::ConnectionClosed,
+                        Err(ref e) => connection_lost_error(e),
+                    };
+                    let _ = reply_tx.send(Err(err));
+                }
+
+                Ok(())
+            });
+
+            // The next request tries again if the SUB couldn't be sent
+            if let Err(e) = tx.try_send(Op::SUB(sub_cmd.clone())) {
+                rx.remove_sid(&sub_cmd.sid);
+                return Err(e);
+            }
+
+            tokio_executor::spawn(routing);
+            *sid = Some(sub_cmd.sid);
+        }
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let mut pending = self.pending.lock();
+        let token = loop {
+            let token = RequestMultiplexer::generate_token();
+            if !pending.contains_key(&token) {
+                break token;
+            }
+        };
+
+        pending.insert(token.clone(), reply_tx);
+        Ok(PendingReply {
+            token,
+            pending: Arc::clone(&self.pending),
+            rx: reply_rx,
+        })
+    }
+}
+
+/// Reply of a muxed request, which is forgotten about once dropped, for instance when the request timed out
+#[derive(Debug)]
+pub(crate) struct PendingReply {
+    token: String,
+    pending: Arc<Mutex<HashMap<String, ReplySender>>>,
+    rx: oneshot::Receiver<Result<Message, NatsError>>,
+}
+
+impl PendingReply {
+    /// Token identifying the request in the shared inbox
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+impl Future for PendingReply {
+    type Item = Message;
+    type Error = NatsError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.rx.poll() {
+            Ok(Async::Ready(res)) => res.map(Async::Ready),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(_) => Err(NatsError::InnerBrokenChain),
+        }
+    }
+}
+
+impl Drop for PendingReply {
+    fn drop(&mut self) {
+        // Once the reply got routed, the token may have been handed to another request, whose sender is left alone
+        self.rx.close();
+        let mut pending = self.pending.lock();
+        let is_ours = match pending.get(&self.token) {
+            Some(reply_tx) => reply_tx.is_canceled(),
+            None => false,
+        };
+
+        if is_ours {
+            pending.remove(&self.token);
+        }
+    }
+}
+
+/// Fails the future with `elapsed` if it isn't resolved after `timeout`, such as `NatsError::RequestTimeout` for
+/// requests
+pub(crate) fn with_timeout<F>(
+    fut: F,
+    timeout: Option<Duration>,
+    elapsed: NatsError,
+) -> impl Future<Item = F::Item, Error = NatsError>
+where
+    F: Future<Error = NatsError>,
+{
+    match timeout {
+        Some(timeout) => Either::A(Timeout::new(fut, timeout).map_err(move |e| map_timeout_error(e, elapsed))),
+        None => Either::B(fut),
+    }
+}
+
+/// Maps the error of a future wrapped in a `tokio_timer::Timeout` back to ours
+fn map_timeout_error(e: ::tokio_timer::timeout::Error<NatsError>, elapsed: NatsError) -> NatsError {
+    if e.is_elapsed() {
+        return elapsed;
+    }
+
+    if e.is_timer() {
+        // Safe to unwrap, we just checked the kind of the error
+        return e.into_timer().unwrap().into();
+    }
+
+    e.into_inner().unwrap_or(NatsError::InnerBrokenChain)
+}
+
+/// Turns a "no responders" status message into the corresponding error
+pub(crate) fn check_reply(msg: Message) -> Result<Message, NatsError> {
+    if msg.is_no_responders() {
+        return Err(NatsError::NoResponders);
+    }
+
+    Ok(msg)
+}
@@ -0,0 +1,237 @@
+use futures::{prelude::*, sync::mpsc};
+
+use error::NatsError;
+use protocol::{commands::Message, SubjectPattern};
+
+/// Number of messages each route holds until they are consumed, beyond which the router stops pulling messages
+/// from the subscription, whose pending limits apply then
+const ROUTE_BUFFER_SIZE: usize = 128;
+
+/// What a `SubjectRouter` does with the messages no route matches when there is no fallback
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnmatchedPolicy {
+    /// The messages are dropped
+    Drop,
+    /// The router fails with `NatsError::UnmatchedSubject`
+    Fail,
+}
+
+impl Default for UnmatchedPolicy {
+    fn default() -> Self {
+        UnmatchedPolicy::Drop
+    }
+}
+
+/// Route a message is dispatched to
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Route(usize),
+    Fallback,
+}
+
+/// Dispatches the messages of a single subscription, typically on a wildcard subject such as `devices.>`, to child
+/// streams by subject pattern, such as `devices.*.telemetry`. Each message goes to the first route matching its
+/// subject, in the order routes were added, or to the fallback if none does.
+///
+/// The router is a future driving the subscription, which must be spawned once the routes are set up. It resolves
+/// once the subscription ends and fails with its error, the child streams ending along with it. Dropping a child
+/// stream removes its route
+#[derive(Debug)]
+pub struct SubjectRouter<S> {
+    stream: S,
+    routes: Vec<(SubjectPattern, mpsc::Sender<Message>)>,
+    fallback: Option<mpsc::Sender<Message>>,
+    unmatched_policy: UnmatchedPolicy,
+    /// Message waiting for room in the buffer of its route
+    pending: Option<(Target, Message)>,
+}
+
+impl<S> SubjectRouter<S>
+where
+    S: Stream<Item = Message, Error = NatsError>,
+{
+    /// Creates a router over the messages of `stream`, such as a `Subscription`
+    pub fn new(stream: S) -> Self {
+        SubjectRouter {
+            stream,
+            routes: vec![],
+            fallback: None,
+            unmatched_policy: UnmatchedPolicy::default(),
+            pending: None,
+        }
+    }
+
+    /// Adds a route and returns the stream of the messages whose subject matches `pattern`
+    pub fn route(&mut self, pattern: SubjectPattern) -> RoutedMessages {
+        let (tx, rx) = mpsc::channel(ROUTE_BUFFER_SIZE);
+        self.routes.push((pattern, tx));
+        RoutedMessages(rx)
+    }
+
+    /// Returns the stream of the messages no route matches, replacing the previous fallback if any
+    pub fn fallback(&mut self) -> RoutedMessages {
+        let (tx, rx) = mpsc::channel(ROUTE_BUFFER_SIZE);
+        self.fallback = Some(tx);
+        RoutedMessages(rx)
+    }
+
+    /// Sets what to do with the messages no route matches when there is no fallback
+    pub fn unmatched_policy(&mut self, policy: UnmatchedPolicy) -> &mut Self {
+        self.unmatched_policy = policy;
+        self
+    }
+
+    fn target_for(&self, subject: &str) -> Option<Target> {
+        self.routes
+            .iter()
+            .position(|(pattern, _)| pattern.matches(subject))
+            .map(Target::Route)
+            .or_else(|| self.fallback.as_ref().map(|_| Target::Fallback))
+    }
+
+    /// Finds the route of a message, applying the unmatched policy if there is none
+    fn dispatch(&mut self, msg: Message) -> Result<(), NatsError> {
+        match self.target_for(&msg.subject) {
+            Some(target) => self.pending = Some((target, msg)),
+            None => match self.unmatched_policy {
+                UnmatchedPolicy::Drop => debug!(target: "nitox", "Dropping unmatched message {:?}", msg),
+                UnmatchedPolicy::Fail => return Err(NatsError::UnmatchedSubject(msg.subject)),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Hands the pending message to its route, unless its buffer is full
+    fn poll_pending(&mut self) -> Poll<(), NatsError> {
+        let (target, msg) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(Async::Ready(())),
+        };
+
+        let res = match target {
+            Target::Route(i) => self.routes[i].1.start_send(msg),
+            // Only targeted while there is a fallback
+            Target::Fallback => self.fallback.as_mut().unwrap().start_send(msg),
+        };
+
+        match res {
+            Ok(AsyncSink::Ready) => Ok(Async::Ready(())),
+            Ok(AsyncSink::NotReady(msg)) => {
+                self.pending = Some((target, msg));
+                Ok(Async::NotReady)
+            }
+            // The stream of the route got dropped, so the message goes to the next one matching
+            Err(e) => {
+                match target {
+                    Target::Route(i) => {
+                        debug!(target: "nitox", "Removing dropped route {}", self.routes[i].0);
+                        self.routes.remove(i);
+                    }
+                    Target::Fallback => self.fallback = None,
+                }
+
+                self.dispatch(e.into_inner())?;
+                self.poll_pending()
+            }
+        }
+    }
+}
+
+impl<S> Future for SubjectRouter<S>
+where
+    S: Stream<Item = Message, Error = NatsError>,
+{
+    type Item = ();
+    type Error = NatsError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if let Async::NotReady = self.poll_pending()? {
+                return Ok(Async::NotReady);
+            }
+
+            match self.stream.poll()? {
+                Async::Ready(Some(msg)) => self.dispatch(msg)?,
+                Async::Ready(None) => return Ok(Async::Ready(())),
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+/// Stream of the messages dispatched to a route of a `SubjectRouter`
+#[derive(Debug)]
+pub struct RoutedMessages(mpsc::Receiver<Message>);
+
+impl Stream for RoutedMessages {
+    type Item = Message;
+    type Error = NatsError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.0.poll().map_err(|_| NatsError::InnerBrokenChain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubjectRouter, UnmatchedPolicy};
+    use error::NatsError;
+    use futures::{prelude::*, stream};
+    use protocol::commands::Message;
+
+    fn messages(subjects: &[&str]) -> impl Stream<Item = Message, Error = NatsError> {
+        let messages: Vec<_> = subjects
+            .iter()
+            .map(|subject| Message::builder().subject(*subject).sid("1").payload("").build().unwrap())
+            .collect();
+        stream::iter_ok(messages)
+    }
+
+    fn subjects(routed: impl Stream<Item = Message, Error = NatsError>) -> Vec<String> {
+        routed.map(|msg| msg.subject).collect().wait().unwrap()
+    }
+
+    #[test]
+    fn it_routes_messages() {
+        let mut router = SubjectRouter::new(messages(&[
+            "devices.1.telemetry",
+            "devices.1.status",
+            "devices.2.telemetry",
+            "devices.2.alerts",
+        ]));
+        let telemetry = router.route("devices.*.telemetry".parse().unwrap());
+        let device = router.route("devices.1.>".parse().unwrap());
+        let fallback = router.fallback();
+
+        assert!(router.wait().is_ok());
+        assert_eq!(subjects(telemetry), vec!["devices.1.telemetry", "devices.2.telemetry"]);
+        assert_eq!(subjects(device), vec!["devices.1.status"]);
+        assert_eq!(subjects(fallback), vec!["devices.2.alerts"]);
+    }
+
+    #[test]
+    fn it_reroutes_after_dropped_routes() {
+        let mut router = SubjectRouter::new(messages(&["devices.1.telemetry", "devices.2.telemetry"]));
+        drop(router.route("devices.1.*".parse().unwrap()));
+        let telemetry = router.route("devices.*.telemetry".parse().unwrap());
+
+        assert!(router.wait().is_ok());
+        assert_eq!(subjects(telemetry), vec!["devices.1.telemetry", "devices.2.telemetry"]);
+    }
+
+    #[test]
+    fn it_handles_unmatched_messages() {
+        let mut router = SubjectRouter::new(messages(&["devices.1.status", "devices.1.telemetry"]));
+        let telemetry = router.route("devices.*.telemetry".parse().unwrap());
+        assert!(router.wait().is_ok());
+        assert_eq!(subjects(telemetry), vec!["devices.1.telemetry"]);
+
+        let mut router = SubjectRouter::new(messages(&["devices.1.status", "devices.1.telemetry"]));
+        router.unmatched_policy(UnmatchedPolicy::Fail);
+        match router.wait() {
+            Err(NatsError::UnmatchedSubject(ref subject)) if subject == "devices.1.status" => {}
+            res => panic!("Expected the router to fail on devices.1.status, got {:?}", res),
+        }
+    }
+}
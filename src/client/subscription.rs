@@ -0,0 +1,245 @@
+use futures::prelude::*;
+use serde::de::DeserializeOwned;
+use serde_json as json;
+use std::{fmt, marker::PhantomData, sync::Arc};
+
+use error::NatsError;
+use protocol::{
+    commands::{Message, SubCommand, UnsubCommand},
+    Op,
+};
+
+use super::{
+    drain_sids, keep_open, pending::PendingMessages, ping::PongWaiters, CloseGuard, NatsClientMultiplexer,
+    NatsClientSender,
+};
+
+/// Default maximum number of messages a subscription holds until they are consumed
+pub const DEFAULT_PENDING_MSGS_LIMIT: usize = 512 * 1024;
+/// Default maximum size of the payloads a subscription holds until they are consumed: 64MB
+pub const DEFAULT_PENDING_BYTES_LIMIT: usize = 64 * 1024 * 1024;
+
+/// What happens to the messages of a subscription that reached its pending limits, as it doesn't consume them fast
+/// enough. Dropped messages are reported through `ClientEvent::SlowConsumer`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlowConsumerPolicy {
+    /// Incoming messages are dropped until there is room again
+    DropNew,
+    /// The oldest messages are dropped to make room for the incoming ones
+    DropOldest,
+    /// The stream ends with `NatsError::SlowConsumer` after the messages it holds
+    Error,
+}
+
+impl Default for SlowConsumerPolicy {
+    fn default() -> Self {
+        SlowConsumerPolicy::DropNew
+    }
+}
+
+/// Options that can be given to `NatsClient::subscribe_with_options`
+#[derive(Debug, Clone, Builder)]
+#[builder(setter(into))]
+pub struct SubscribeOptions {
+    /// Maximum number of messages held until they are consumed, without limit if `None`
+    #[builder(default = "Some(DEFAULT_PENDING_MSGS_LIMIT)")]
+    pub pending_msgs_limit: Option<usize>,
+    /// Maximum size in bytes of the payloads held until they are consumed, without limit if `None`
+    #[builder(default = "Some(DEFAULT_PENDING_BYTES_LIMIT)")]
+    pub pending_bytes_limit: Option<usize>,
+    /// What to do with the messages coming once a limit is reached
+    #[builder(default)]
+    pub slow_consumer_policy: SlowConsumerPolicy,
+}
+
+impl Default for SubscribeOptions {
+    fn default() -> Self {
+        SubscribeOptions {
+            pending_msgs_limit: Some(DEFAULT_PENDING_MSGS_LIMIT),
+            pending_bytes_limit: Some(DEFAULT_PENDING_BYTES_LIMIT),
+            slow_consumer_policy: SlowConsumerPolicy::default(),
+        }
+    }
+}
+
+impl SubscribeOptions {
+    pub fn builder() -> SubscribeOptionsBuilder {
+        SubscribeOptionsBuilder::default()
+    }
+
+    /// Options of the internal subscriptions of requests, whose replies are consumed right away
+    pub(crate) fn unlimited() -> Self {
+        SubscribeOptions {
+            pending_msgs_limit: None,
+            pending_bytes_limit: None,
+            slow_consumer_policy: SlowConsumerPolicy::default(),
+        }
+    }
+}
+
+/// Subscription made through `NatsClient::subscribe`, which is a `Stream` of its messages. Dropping it unsubscribes
+/// from the server if that's not done already
+pub struct Subscription {
+    /// SUB command the subscription was made with
+    cmd: SubCommand,
+    /// Messages of the subscription, coming from the multiplexer
+    stream: Box<dyn Stream<Item = Message, Error = NatsError> + Send + Sync>,
+    /// Sink part to send commands
+    tx: NatsClientSender,
+    /// Subscription multiplexer
+    rx: Arc<NatsClientMultiplexer>,
+    /// Messages waiting to be consumed
+    pending: Arc<PendingMessages>,
+    /// PINGs waiting for their PONG, for draining
+    pongs: Arc<PongWaiters>,
+    /// Keeps the connection open as long as the subscription is around
+    guard: Arc<CloseGuard>,
+}
+
+impl fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("cmd", &self.cmd)
+            .field("stream", &"Box<Stream>...")
+            .finish()
+    }
+}
+
+impl Subscription {
+    pub(crate) fn new(
+        cmd: SubCommand,
+        stream: Box<dyn Stream<Item = Message, Error = NatsError> + Send + Sync>,
+        tx: NatsClientSender,
+        rx: Arc<NatsClientMultiplexer>,
+        pending: Arc<PendingMessages>,
+        pongs: Arc<PongWaiters>,
+        guard: Arc<CloseGuard>,
+    ) -> Self {
+        Subscription {
+            cmd,
+            stream,
+            tx,
+            rx,
+            pending,
+            pongs,
+            guard,
+        }
+    }
+
+    /// Subscription ID of the subscription
+    pub fn sid(&self) -> &str {
+        &self.cmd.sid
+    }
+
+    /// Subject the subscription receives the messages of, which may contain wildcards
+    pub fn subject(&self) -> &str {
+        &self.cmd.subject
+    }
+
+    /// Queue group the subscription belongs to, if any
+    pub fn queue_group(&self) -> Option<&str> {
+        self.cmd.queue_group.as_deref()
+    }
+
+    /// Number of messages dropped so far because the pending limits of the subscription were reached
+    pub fn dropped(&self) -> u64 {
+        self.pending.dropped()
+    }
+
+    /// Sends a UNSUB command to the server; The stream ends once the messages already received are consumed
+    ///
+    /// Returns `impl Future<Item = (), Error = NatsError>`
+    pub fn unsubscribe(&self) -> impl Future<Item = (), Error = NatsError> + Send + Sync {
+        self.rx.remove_sid(&self.cmd.sid);
+        keep_open(&self.guard, self.tx.send(Op::UNSUB(self.unsub_cmd(None))))
+    }
+
+    /// Lets the server unsubscribe automatically once `max_msgs` messages have been received in total, the stream
+    /// ending right after the last of them
+    ///
+    /// Returns `impl Future<Item = (), Error = NatsError>`
+    pub fn unsubscribe_after(&self, max_msgs: u32) -> impl Future<Item = (), Error = NatsError> + Send + Sync {
+        self.rx.set_max_msgs(&self.cmd.sid, max_msgs);
+        keep_open(&self.guard, self.tx.send(Op::UNSUB(self.unsub_cmd(Some(max_msgs)))))
+    }
+
+    /// Unsubscribes while still delivering the messages the server sent before it processed the UNSUB, the stream
+    /// ending right after them. See `NatsClient::drain_subscription`
+    ///
+    /// Returns `impl Future<Item = (), Error = NatsError>`
+    pub fn drain(&self) -> impl Future<Item = (), Error = NatsError> + Send + Sync {
+        self.rx.start_draining(&self.cmd.sid);
+        keep_open(
+            &self.guard,
+            drain_sids(&self.tx, &self.rx, &self.pongs, vec![self.cmd.sid.clone()]),
+        )
+    }
+
+    fn unsub_cmd(&self, max_msgs: Option<u32>) -> UnsubCommand {
+        UnsubCommand {
+            sid: self.cmd.sid.clone(),
+            max_msgs,
+        }
+    }
+}
+
+impl Stream for Subscription {
+    type Error = NatsError;
+    type Item = Message;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.stream.poll()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // Nothing to tell the server if the subscription already ended
+        if self.rx.remove_sid(&self.cmd.sid) {
+            debug!(target: "nitox", "Unsubscribing dropped subscription {}", self.cmd.sid);
+            let _ = self.tx.try_send(Op::UNSUB(self.unsub_cmd(None)));
+        }
+    }
+}
+
+/// Subscription made through `NatsClient::subscribe_json`, which is a `Stream` of its messages along with their
+/// payload decoded from JSON. A payload that can't be decoded is reported as a `NatsError::JsonError` item, without
+/// ending the stream
+#[derive(Debug)]
+pub struct JsonSubscription<T> {
+    subscription: Subscription,
+    _payload: PhantomData<fn() -> T>,
+}
+
+impl<T> JsonSubscription<T> {
+    pub(crate) fn new(subscription: Subscription) -> Self {
+        JsonSubscription {
+            subscription,
+            _payload: PhantomData,
+        }
+    }
+
+    /// Underlying subscription, to unsubscribe or drain it
+    pub fn subscription(&self) -> &Subscription {
+        &self.subscription
+    }
+
+    /// Returns the underlying subscription, yielding the messages without decoding them
+    pub fn into_inner(self) -> Subscription {
+        self.subscription
+    }
+}
+
+impl<T: DeserializeOwned> Stream for JsonSubscription<T> {
+    type Error = NatsError;
+    type Item = Result<(T, Message), NatsError>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        Ok(self.subscription.poll()?.map(|maybe_msg| {
+            maybe_msg.map(|msg| match json::from_slice(&msg.payload) {
+                Ok(value) => Ok((value, msg)),
+                Err(e) => Err(NatsError::JsonError(e)),
+            })
+        }))
+    }
+}
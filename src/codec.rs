@@ -1,13 +1,22 @@
 use bytes::{BufMut, BytesMut};
 use error::NatsError;
-use protocol::{CommandError, Op};
+use protocol::{
+    commands::{Message, PubCommand},
+    parse_payload_len, Command, CommandError, Op,
+};
 use tokio_codec::{Decoder, Encoder};
 
+/// Largest payload length accepted until the server announces its own limit, which is the highest value a server
+/// can be configured with
+const MAX_PAYLOAD_LIMIT: usize = 64 * 1024 * 1024;
+
 /// `tokio-codec` implementation of the protocol parsing
 #[derive(Default, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct OpCodec {
     /// Used as an optimization for buffer lookup
     next_index: usize,
+    /// `max_payload` of the last INFO decoded, if any
+    max_payload: Option<usize>,
 }
 
 impl OpCodec {
@@ -16,6 +25,14 @@ impl OpCodec {
     }
 }
 
+/// Whether the command carries a payload whose length is announced at the end of its command line
+fn is_length_prefixed(cmd_name: &[u8]) -> bool {
+    cmd_name == PubCommand::CMD_NAME
+        || cmd_name == Message::CMD_NAME
+        || cmd_name == PubCommand::HEADERS_CMD_NAME
+        || cmd_name == Message::HEADERS_CMD_NAME
+}
+
 impl Encoder for OpCodec {
     type Error = NatsError;
     type Item = Op;
@@ -53,15 +70,38 @@ impl Decoder for OpCodec {
             if let Some(command_body_offset) = buf[command_end..].windows(2).position(|w| w == b"\r\n") {
                 let mut end_buf_pos = command_end + command_body_offset + 2;
 
-                if &buf[..command_end] == b"PUB" || &buf[..command_end] == b"MSG" {
-                    debug!(target: "nitox", "detected PUB or MSG, looking for second CRLF");
-                    if let Some(new_end) = buf[end_buf_pos..].windows(2).position(|w| w == b"\r\n") {
-                        debug!(target: "nitox", "found second CRLF at position {}", end_buf_pos + new_end + 2);
-                        end_buf_pos += new_end + 2;
-                    } else {
+                if is_length_prefixed(&buf[..command_end]) {
+                    // The payload may contain anything, CRLF included, so we rely on the announced length
+                    // instead of looking for the next CRLF
+                    let payload_len = match parse_payload_len(&buf[..end_buf_pos - 2]) {
+                        Ok(payload_len) => payload_len,
+                        Err(e) => {
+                            debug!(target: "nitox", "command header couldn't be parsed {}", e);
+                            self.next_index = 0;
+                            return Err(e.into());
+                        }
+                    };
+
+                    // The length is checked before reserving room for the payload, so that a bogus one cannot
+                    // make us allocate unbounded amounts of memory
+                    let max_payload = self.max_payload.unwrap_or(MAX_PAYLOAD_LIMIT);
+                    let frame_end = match end_buf_pos.checked_add(payload_len).and_then(|end| end.checked_add(2)) {
+                        Some(frame_end) if payload_len <= max_payload => frame_end,
+                        _ => {
+                            debug!(target: "nitox", "announced payload length {} exceeds {}", payload_len, max_payload);
+                            self.next_index = 0;
+                            return Err(CommandError::CommandMalformed.into());
+                        }
+                    };
+
+                    debug!(target: "nitox", "detected PUB/MSG, waiting for {} bytes of payload", payload_len);
+                    if buf.len() < frame_end {
                         debug!(target: "nitox", "command was incomplete");
+                        buf.reserve(frame_end - buf.len());
                         return Ok(None);
                     }
+
+                    end_buf_pos = frame_end;
                 }
 
                 debug!(target: "nitox", "codec detected command body {:?}", &buf[..end_buf_pos]);
@@ -73,6 +113,9 @@ impl Decoder for OpCodec {
                     }
                     Ok(op) => {
                         debug!(target: "nitox", "codec parsed command {:#?}", op);
+                        if let Op::INFO(ref server_info) = op {
+                            self.max_payload = Some(server_info.max_payload as usize);
+                        }
                         let _ = buf.split_to(end_buf_pos);
                         debug!(target: "nitox", "buffer now contains {:?}", buf);
                         self.next_index = 0;
@@ -95,3 +138,152 @@ impl Decoder for OpCodec {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::OpCodec;
+    use bytes::{BufMut, BytesMut};
+    use protocol::{commands::*, Op};
+    use tokio_codec::{Decoder, Encoder};
+
+    fn roundtrip(op: Op) -> Option<Op> {
+        let mut codec = OpCodec::default();
+        let mut buf = BytesMut::new();
+        codec.encode(op, &mut buf).unwrap();
+        let decoded = codec.decode(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn it_roundtrips_err() {
+        let op = Op::ERR(ServerError::from("Authorization Violation".to_string()));
+        match roundtrip(op.clone()) {
+            Some(Op::ERR(ref err)) => assert!(err.is_authorization_violation()),
+            decoded => panic!("Expected an -ERR, got {:?}", decoded),
+        }
+        assert_eq!(roundtrip(op.clone()), Some(op));
+    }
+
+    #[test]
+    fn it_rejects_oversized_payload_lengths() {
+        let mut codec = OpCodec::default();
+        let mut buf = BytesMut::from(format!("MSG foo 1 {}\r\n", usize::MAX));
+        assert!(codec.decode(&mut buf).is_err());
+
+        let mut buf = BytesMut::from(&b"MSG foo 1 4000000000\r\n"[..]);
+        assert!(codec.decode(&mut buf).is_err());
+        assert!(buf.capacity() < 4_000_000_000);
+
+        let info = ServerInfo::builder()
+            .server_id("test")
+            .version("1.3.0")
+            .go("go1.10.3")
+            .host("0.0.0.0")
+            .port(4222u32)
+            .max_payload(4u32)
+            .build()
+            .unwrap();
+        let mut buf = BytesMut::new();
+        codec.encode(Op::INFO(info), &mut buf).unwrap();
+        buf.extend_from_slice(&b"MSG foo 1 4\r\nbarz\r\nMSG foo 1 5\r\n"[..]);
+        assert!(matches!(codec.decode(&mut buf), Ok(Some(Op::INFO(_)))));
+        assert!(matches!(codec.decode(&mut buf), Ok(Some(Op::MSG(_)))));
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn it_roundtrips_msg(
+            subject in "[a-zA-Z0-9]{1,8}(\\.[a-zA-Z0-9]{1,8}){0,3}",
+            sid in "[a-zA-Z0-9]{1,12}",
+            reply_to in proptest::option::of("[a-zA-Z0-9]{1,8}(\\.[a-zA-Z0-9]{1,8}){0,3}"),
+            payload in proptest::collection::vec(proptest::num::u8::ANY, 0..1024)
+        ) {
+            let op = Op::MSG(Message {
+                subject,
+                sid,
+                reply_to,
+                payload: payload.into(),
+                headers: None,
+            });
+
+            prop_assert_eq!(roundtrip(op.clone()), Some(op));
+        }
+
+        #[test]
+        fn it_roundtrips_hmsg(
+            sid in "[a-zA-Z0-9]{1,12}",
+            headers in proptest::collection::vec(("[a-zA-Z0-9-]{1,16}", "[ -~]{0,32}"), 0..8),
+            payload in proptest::collection::vec(proptest::num::u8::ANY, 0..1024)
+        ) {
+            let mut header_map = HeaderMap::new();
+            for (name, value) in headers {
+                header_map.append(name, value.trim());
+            }
+
+            let op = Op::MSG(Message {
+                subject: "FOO".into(),
+                sid,
+                reply_to: None,
+                payload: payload.into(),
+                headers: Some(header_map),
+            });
+
+            prop_assert_eq!(roundtrip(op.clone()), Some(op));
+        }
+
+        #[test]
+        fn it_roundtrips_pub(
+            subject in "[a-zA-Z0-9]{1,8}(\\.[a-zA-Z0-9]{1,8}){0,3}",
+            reply_to in proptest::option::of("[a-zA-Z0-9]{1,8}(\\.[a-zA-Z0-9]{1,8}){0,3}"),
+            payload in proptest::collection::vec(proptest::num::u8::ANY, 0..1024)
+        ) {
+            let op = Op::PUB(PubCommand {
+                subject,
+                reply_to,
+                payload: payload.into(),
+                headers: None,
+            });
+
+            prop_assert_eq!(roundtrip(op.clone()), Some(op));
+        }
+
+        #[test]
+        fn it_decodes_fragmented_frames(
+            payloads in proptest::collection::vec(proptest::collection::vec(proptest::num::u8::ANY, 0..64), 1..8),
+            chunk_size in 1usize..32
+        ) {
+            let ops: Vec<Op> = payloads
+                .into_iter()
+                .map(|payload| {
+                    Op::MSG(Message {
+                        subject: "FOO".into(),
+                        sid: "pouet".into(),
+                        reply_to: None,
+                        payload: payload.into(),
+                        headers: None,
+                    })
+                }).collect();
+
+            let mut codec = OpCodec::default();
+            let mut wire = BytesMut::new();
+            for op in ops.iter().cloned() {
+                codec.encode(op, &mut wire).unwrap();
+            }
+
+            let mut buf = BytesMut::new();
+            let mut decoded = vec![];
+            for chunk in wire.chunks(chunk_size) {
+                buf.reserve(chunk.len());
+                buf.put(chunk);
+                while let Some(op) = codec.decode(&mut buf).unwrap() {
+                    decoded.push(op);
+                }
+            }
+
+            prop_assert!(buf.is_empty());
+            prop_assert_eq!(decoded, ops);
+        }
+    }
+}
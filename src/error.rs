@@ -30,12 +30,21 @@ pub enum NatsError {
     /// Occurs if we try to parse a string that is supposed to be valid UTF8 and...is actually not
     #[fail(display = "UTF8Error: {}", _0)]
     UTF8Error(::std::string::FromUtf8Error),
-    /// Error on TLS handling
+    /// Error on TLS handling, coming from the TLS backend selected through the `native-tls` or `rustls` features
     #[fail(display = "TlsError: {}", _0)]
-    TlsError(::native_tls::Error),
+    TlsError(Box<dyn std::error::Error + Send + Sync>),
     /// Occurs when the host is not provided, removing the ability for TLS to function correctly for server identify verification
     #[fail(display = "TlsHostMissingError: Host is missing, can't verify server identity")]
     TlsHostMissingError,
+    /// The server requires TLS, but the client disabled it in its `TlsOptions`
+    #[fail(display = "TlsRequiredByServerError: the server requires TLS, which is disabled on the client")]
+    TlsRequiredByServerError,
+    /// The client requires TLS, which is disabled in its `TlsOptions` at the same time
+    #[fail(display = "TlsDisabledError: TLS is required by the client, but disabled in its TLS options")]
+    TlsDisabledError,
+    /// The client requires TLS, but the server doesn't support it
+    #[fail(display = "TlsNotAvailableError: TLS is required by the client, but not available on the server")]
+    TlsNotAvailableError,
     /// Cannot parse an URL
     #[fail(display = "UrlParseError: {}", _0)]
     UrlParseError(::url::ParseError),
@@ -47,9 +56,12 @@ pub enum NatsError {
     /// resolving succeeded but gave no results
     #[fail(display = "UriDNSResolveError: {:?}", _0)]
     UriDNSResolveError(Option<io::Error>),
-    /// Cannot reconnect to server after retrying once
+    /// Cannot reconnect to server, the attempts allowed by the `ReconnectPolicy` being exhausted
     #[fail(display = "CannotReconnectToServer: cannot reconnect to server")]
     CannotReconnectToServer,
+    /// The connection has been closed by the client, such as after a drain
+    #[fail(display = "ConnectionClosed: the connection has been closed")]
+    ConnectionClosed,
     /// Something went wrong in one of the Reciever/Sender pairs
     #[fail(display = "InnerBrokenChain: the sender/receiver pair has been disconnected")]
     InnerBrokenChain,
@@ -59,9 +71,54 @@ pub enum NatsError {
         _0
     )]
     MaxPayloadOverflow(u32),
+    /// The user tried to send headers to a server that doesn't support them (headers require NATS 2.2+), or
+    /// while the CONNECT command turned them off
+    #[fail(display = "HeadersNotSupported: message headers are either not supported by the server or turned off")]
+    HeadersNotSupported,
+    /// The server didn't confirm the connection before its deadline
+    #[fail(display = "ConnectTimeout: the server didn't confirm the connection in time")]
+    ConnectTimeout,
+    /// A request didn't get any reply before its deadline
+    #[fail(display = "RequestTimeout: the request didn't get a reply in time")]
+    RequestTimeout,
+    /// The server didn't answer the PING of a flush before its deadline
+    #[fail(display = "FlushTimeout: the server didn't confirm the flush in time")]
+    FlushTimeout,
+    /// The server reported that nobody is subscribed to the subject of a request
+    #[fail(display = "NoResponders: nobody is listening to the request subject")]
+    NoResponders,
+    /// Error from the timer backing timeouts, usually because no timer is running on the current executor
+    #[fail(display = "TimerError: {}", _0)]
+    TimerError(::tokio_timer::Error),
+    /// A message was published while reconnecting, but the buffer holding such messages until we are connected
+    /// again is full
+    #[fail(display = "ReconnectBufferFull: the reconnect buffer cannot hold more messages")]
+    ReconnectBufferFull,
+    /// The server rejected a command sent in verbose mode, or closed the connection because of an error
+    #[fail(display = "ServerError: {}", _0)]
+    ServerError(protocol::commands::ServerError),
+    /// The server rejected the credentials sent along the CONNECT command
+    #[fail(display = "AuthorizationViolation: the server rejected the credentials")]
+    AuthorizationViolation,
+    /// An NKey seed or public key is malformed, or a signature is invalid
+    #[fail(display = "NKeyError: {}", _0)]
+    NKeyError(String),
+    /// A `.creds` file doesn't hold a user JWT followed by an NKey seed in armored blocks
+    #[fail(display = "CredsParseError: {}", _0)]
+    CredsParseError(String),
+    /// A payload could not be encoded to JSON or decoded from it
+    #[fail(display = "JSONError: {}", _0)]
+    JsonError(::serde_json::Error),
     /// Generic string error
     #[fail(display = "GenericError: {}", _0)]
     GenericError(String),
+    /// A subscription reached its pending limits with `SlowConsumerPolicy::Error`, as it doesn't consume its messages
+    /// fast enough
+    #[fail(display = "SlowConsumer: the subscription reached its pending limits")]
+    SlowConsumer,
+    /// A `SubjectRouter` with `UnmatchedPolicy::Fail` received a message on a subject none of its routes matches
+    #[fail(display = "UnmatchedSubject: no route matches the subject {}", _0)]
+    UnmatchedSubject(String),
     /// Error thrown when a subscription is fused after reaching the maximum messages
     #[fail(display = "SubscriptionReachedMaxMsgs after {} messages", _0)]
     SubscriptionReachedMaxMsgs(u32),
@@ -70,7 +127,11 @@ pub enum NatsError {
 impl From<io::Error> for NatsError {
     fn from(err: io::Error) -> Self {
         match err.kind() {
-            io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused => {
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof => {
                 NatsError::ServerDisconnected(Some(err))
             }
             _ => NatsError::IOError(err),
@@ -85,8 +146,17 @@ impl<T> From<::futures::sync::mpsc::SendError<T>> for NatsError {
 }
 
 from_error!(protocol::CommandError, NatsError, NatsError::ProtocolError);
+from_error!(protocol::ArgumentValidationError, NatsError, NatsError::ProtocolError);
 from_error!(::std::string::FromUtf8Error, NatsError, NatsError::UTF8Error);
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
 from_error!(::native_tls::Error, NatsError, NatsError::TlsError);
+#[cfg(feature = "rustls")]
+from_error!(::rustls::TLSError, NatsError, NatsError::TlsError);
+#[cfg(feature = "rustls")]
+from_error!(::tokio_rustls::webpki::Error, NatsError, NatsError::TlsError);
+#[cfg(feature = "rustls")]
+from_error!(::tokio_rustls::webpki::InvalidDNSNameError, NatsError, NatsError::TlsError);
 from_error!(String, NatsError, NatsError::GenericError);
+from_error!(::tokio_timer::Error, NatsError, NatsError::TimerError);
 from_error!(::url::ParseError, NatsError, NatsError::UrlParseError);
 from_error!(::std::net::AddrParseError, NatsError, NatsError::AddrParseError);
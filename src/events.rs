@@ -0,0 +1,120 @@
+use futures::sync::mpsc;
+use parking_lot::Mutex;
+
+use protocol::commands::{ServerError, ServerInfo};
+
+/// Lifecycle events of the connection of a `NatsClient`, observable through `NatsClient::events()`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// The client is connected to the server at the given `host:port` address
+    Connected(String),
+    /// The connection to the server has been lost, for the given reason
+    Disconnected(String),
+    /// The given reconnection attempt is about to be made, starting from 1
+    Reconnecting(u32),
+    /// The connection has been re-established and the subscriptions restored
+    Reconnected,
+    /// The server sent an INFO message, either upon connection or because the cluster changed
+    ServerInfoUpdated(ServerInfo),
+    /// The server sent an -ERR message
+    ServerError(ServerError),
+    /// The subscription of the given sid reached its pending limits, the given number of messages having been
+    /// dropped so far. Reported again once it caught up and falls behind anew
+    SlowConsumer(String, u64),
+    /// A message published on the given subject while reconnecting was dropped, the buffer holding such messages
+    /// until we are connected again being full
+    ReconnectBufferFull(String),
+    /// The server is shutting down gracefully and asks its clients to move to another server of the cluster
+    LameDuckMode,
+    /// The client is unusable for good, no more events will be emitted
+    Closed,
+}
+
+#[derive(Debug, Default)]
+struct EventBroadcasterInner {
+    listeners: Vec<mpsc::UnboundedSender<ClientEvent>>,
+    /// Address of the server we are connected to, if any
+    connected_to: Option<String>,
+    /// Whether `ClientEvent::Closed` has been emitted
+    closed: bool,
+}
+
+/// Forwards the events of a client to all of its listeners
+#[derive(Debug, Default)]
+pub(crate) struct EventBroadcaster {
+    inner: Mutex<EventBroadcasterInner>,
+}
+
+impl EventBroadcaster {
+    pub fn new() -> Self {
+        EventBroadcaster::default()
+    }
+
+    /// Sends an event to every listener, forgetting about the ones that are gone
+    pub fn emit(&self, event: ClientEvent) {
+        debug!(target: "nitox", "Emitting client event {:?}", event);
+        let mut inner = self.inner.lock();
+        match event {
+            ClientEvent::Connected(ref server) => inner.connected_to = Some(server.clone()),
+            ClientEvent::Disconnected(_) | ClientEvent::Closed => inner.connected_to = None,
+            _ => {}
+        }
+
+        inner.listeners.retain(|tx| tx.unbounded_send(event.clone()).is_ok());
+
+        // Ends the streams of the listeners
+        if event == ClientEvent::Closed {
+            inner.closed = true;
+            inner.listeners.clear();
+        }
+    }
+
+    /// Returns a new stream of events, starting with `ClientEvent::Connected` if we are connected at that time.
+    /// The stream is empty if the client is already closed
+    pub fn listen(&self) -> mpsc::UnboundedReceiver<ClientEvent> {
+        let (tx, rx) = mpsc::unbounded();
+        let mut inner = self.inner.lock();
+        if let Some(ref server) = inner.connected_to {
+            let _ = tx.unbounded_send(ClientEvent::Connected(server.clone()));
+        }
+
+        if !inner.closed {
+            inner.listeners.push(tx);
+        }
+
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientEvent, EventBroadcaster};
+    use futures::prelude::*;
+
+    #[test]
+    fn it_broadcasts_events() {
+        let events = EventBroadcaster::new();
+        let first = events.listen();
+        events.emit(ClientEvent::Connected("127.0.0.1:4222".into()));
+        // Late listeners learn about the current connection
+        let second = events.listen();
+        events.emit(ClientEvent::Disconnected("Connection closed by the server".into()));
+        events.emit(ClientEvent::Closed);
+
+        let first: Vec<ClientEvent> = first.collect().wait().unwrap();
+        let second: Vec<ClientEvent> = second.collect().wait().unwrap();
+        assert_eq!(first.len(), 3);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn it_ends_streams_once_closed() {
+        let events = EventBroadcaster::new();
+        events.emit(ClientEvent::Connected("127.0.0.1:4222".into()));
+        events.emit(ClientEvent::Disconnected("Connection closed by the server".into()));
+        events.emit(ClientEvent::Closed);
+
+        let listener: Vec<ClientEvent> = events.listen().collect().wait().unwrap();
+        assert!(listener.is_empty());
+    }
+}
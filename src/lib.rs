@@ -22,6 +22,14 @@
 ///! nitox = "0.1"
 ///! ```
 ///!
+///! TLS relies on the platform's implementation by default (OpenSSL on Linux). To use `rustls` instead, which
+///! doesn't need OpenSSL, for instance in static musl builds:
+///!
+///! ```toml
+///! [dependencies]
+///! nitox = { version = "0.1", default-features = false, features = ["rustls"] }
+///! ```
+///!
 ///! ## Usage
 ///!
 ///! ```rust
@@ -85,6 +93,8 @@ extern crate serde_derive;
 extern crate serde_json;
 
 extern crate bytes;
+extern crate data_encoding;
+extern crate ed25519_dalek;
 extern crate parking_lot;
 extern crate rand;
 
@@ -92,17 +102,34 @@ extern crate rand;
 extern crate log;
 
 extern crate futures;
-extern crate native_tls;
 extern crate tokio_codec;
 extern crate tokio_executor;
 extern crate tokio_tcp;
-extern crate tokio_tls;
+extern crate tokio_timer;
 extern crate url;
 
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+extern crate native_tls;
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+extern crate tokio_tls;
+
+#[cfg(feature = "rustls")]
+extern crate rustls;
+#[cfg(feature = "rustls")]
+extern crate tokio_rustls;
+#[cfg(feature = "rustls")]
+extern crate webpki_roots;
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("A TLS backend must be selected by enabling either the `native-tls` or the `rustls` feature");
+
+#[cfg(test)]
+#[macro_use]
+extern crate proptest;
+
 #[macro_use]
 mod error;
 
-// TODO: Handle verbose mode
 // TODO: Switch parsing to using `nom`
 // TODO: Support NATS Streaming Server
 
@@ -111,7 +138,14 @@ pub mod codec;
 mod protocol;
 pub use self::protocol::*;
 
+mod auth;
+pub use self::auth::{nkeys, Credentials};
+
 pub(crate) mod net;
+pub use self::net::{ReconnectPolicy, TlsCertificate, TlsIdentity, TlsOptions};
+
+mod events;
+pub use self::events::ClientEvent;
 
 mod client;
 pub use self::client::*;
@@ -1,24 +1,42 @@
 use futures::{
-    future::{self, Either},
+    future::{self, Either, Loop},
     prelude::*,
+    stream,
+    task::AtomicTask,
 };
 use parking_lot::RwLock;
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 use tokio_executor;
+use tokio_timer::Delay;
 
 use error::NatsError;
-use protocol::Op;
+use events::{ClientEvent, EventBroadcaster};
+use protocol::{commands::ServerInfo, Op};
 
-use super::connection_inner::NatsConnectionInner;
+use super::{
+    connection_inner::NatsConnectionInner,
+    server_pool::{ServerAddress, ServerPool},
+    tls::TlsConfig,
+    ReconnectPolicy,
+};
 
 macro_rules! reco {
-    ($conn:ident) => {
-        *$conn.state.write() = NatsConnectionState::Disconnected;
-
-        tokio_executor::spawn($conn.reconnect().map_err(|e| {
-            debug!(target: "nitox", "Reconnection error: {}", e);
-            ()
-        }));
+    ($conn:ident, $reason:expr) => {
+        if $conn.start_reconnecting() {
+            $conn.events.emit(ClientEvent::Disconnected($reason));
+            tokio_executor::spawn($conn.reconnect().map_err(|e| {
+                debug!(target: "nitox", "Reconnection error: {}", e);
+                ()
+            }));
+        }
     };
 }
 
@@ -27,53 +45,400 @@ macro_rules! reco {
 pub(crate) enum NatsConnectionState {
     Connected,
     Reconnecting,
+    /// The reconnect policy ran out of attempts; The connection is unusable for good
     Disconnected,
+    /// The client closed the connection; It is unusable for good as well
+    Closed,
+}
+
+type ReconnectFn = dyn Fn(&ServerAddress, &ServerInfo, usize) -> Vec<Op> + Send + Sync;
+
+/// Builds the commands restoring the session (CONNECT, SUBs...) on a freshly reconnected socket, given the server
+/// we reconnected to, the INFO it greeted us with and the number of buffered OPs flushed after these commands
+pub(crate) struct ReconnectHook(Box<ReconnectFn>);
+
+impl ReconnectHook {
+    pub fn new<F: Fn(&ServerAddress, &ServerInfo, usize) -> Vec<Op> + Send + Sync + 'static>(f: F) -> Self {
+        ReconnectHook(Box::new(f))
+    }
+}
+
+/// Reconnect hook slot, shared between the connection and its handles
+type SharedReconnectHook = Arc<RwLock<Option<ReconnectHook>>>;
+
+impl fmt::Debug for ReconnectHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ReconnectHook")
+    }
+}
+
+/// Bounded buffer holding the messages published and the subscriptions changed while the connection is being
+/// re-established. Its size is the sum of the buffered payloads, in bytes
+#[derive(Debug, Default)]
+pub(crate) struct PendingBuffer {
+    ops: VecDeque<Op>,
+    size: usize,
+    capacity: usize,
+}
+
+impl PendingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        PendingBuffer {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    /// Size accounted for an OP in the buffer; Only messages and subscription changes are buffered, the latter
+    /// taking no room
+    fn op_size(op: &Op) -> Option<usize> {
+        match op {
+            Op::PUB(cmd) => Some(cmd.payload.len()),
+            Op::SUB(_) | Op::UNSUB(_) => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Whether a message with a payload of `len` bytes can still be buffered
+    pub fn has_room_for(&self, len: usize) -> bool {
+        self.size + len <= self.capacity
+    }
+
+    /// Buffers a message or a subscription change, giving it back if it isn't one or if the buffer is full
+    pub fn push(&mut self, op: Op) -> Option<Op> {
+        match PendingBuffer::op_size(&op) {
+            Some(len) if self.has_room_for(len) => {
+                self.size += len;
+                self.ops.push_back(op);
+                None
+            }
+            _ => Some(op),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Puts back a message that couldn't be sent at the front of the buffer, regardless of the capacity
+    fn push_front(&mut self, op: Op) {
+        self.size += PendingBuffer::op_size(&op).unwrap_or(0);
+        self.ops.push_front(op);
+    }
+
+    fn pop_front(&mut self) -> Option<Op> {
+        let op = self.ops.pop_front()?;
+        self.size -= PendingBuffer::op_size(&op).unwrap_or(0);
+        Some(op)
+    }
+}
+
+/// Shared view over the state of a `NatsConnection`, which stays usable once the connection has been split
+#[derive(Debug, Clone)]
+pub(crate) struct NatsConnectionHandle {
+    pool: Arc<RwLock<ServerPool>>,
+    state: Arc<RwLock<NatsConnectionState>>,
+    pending: Arc<RwLock<PendingBuffer>>,
+    reconnect_hook: SharedReconnectHook,
+    stale: Arc<AtomicBool>,
+    read_task: Arc<AtomicTask>,
+    write_task: Arc<AtomicTask>,
+}
+
+impl NatsConnectionHandle {
+    /// Checks that a message with a payload of `len` bytes can be sent right now, or at least buffered until we
+    /// are reconnected
+    pub fn check_publish(&self, len: usize) -> Result<(), NatsError> {
+        match *self.state.read() {
+            NatsConnectionState::Connected => Ok(()),
+            NatsConnectionState::Disconnected => Err(NatsError::CannotReconnectToServer),
+            NatsConnectionState::Closed => Err(NatsError::ConnectionClosed),
+            NatsConnectionState::Reconnecting if self.pending.read().has_room_for(len) => Ok(()),
+            NatsConnectionState::Reconnecting => Err(NatsError::ReconnectBufferFull),
+        }
+    }
+
+    /// Returns the server we are connected to, or trying to reconnect to
+    pub fn current_server(&self) -> ServerAddress {
+        self.pool.read().current().clone()
+    }
+
+    /// Sets the hook building the commands replayed after a reconnection
+    pub fn set_reconnect_hook(&self, hook: ReconnectHook) {
+        *self.reconnect_hook.write() = Some(hook);
+    }
+
+    pub fn state(&self) -> NatsConnectionState {
+        *self.state.read()
+    }
+
+    /// Drops the socket and reconnects, as the server stopped answering it. The reconnection is started by the
+    /// `Stream` half, which gets woken up
+    pub fn mark_stale(&self) {
+        self.stale.store(true, Ordering::SeqCst);
+        self.read_task.notify();
+    }
+
+    /// Closes the connection for good, without reconnecting. Both halves fail with `NatsError::ConnectionClosed`
+    /// once woken up, which drops the socket along with them. Returns `false` if it was already closed
+    pub fn close(&self) -> bool {
+        {
+            let mut state = self.state.write();
+            if *state == NatsConnectionState::Closed {
+                return false;
+            }
+
+            *state = NatsConnectionState::Closed;
+        }
+
+        self.read_task.notify();
+        self.write_task.notify();
+        true
+    }
 }
 
 /// Represents a connection to a NATS server. Implements `Sink` and `Stream`
 #[derive(Debug)]
 pub struct NatsConnection {
-    /// indicates if the connection is made over TLS
-    pub(crate) is_tls: bool,
-    /// Server standardized IP address
-    pub(crate) addr: SocketAddr,
-    /// Host of the server; Only used if connecting to a TLS-enabled server
-    pub(crate) host: Option<String>,
+    /// Servers we can connect to, the one we're connected to being at the back
+    pub(crate) pool: Arc<RwLock<ServerPool>>,
+    /// TLS settings of the connections, which are made over plain TCP if `None`
+    pub(crate) tls: Option<TlsConfig>,
     /// Inner dual `Stream`/`Sink` of the TCP connection
     pub(crate) inner: Arc<RwLock<NatsConnectionInner>>,
     /// Current state of the connection
     pub(crate) state: Arc<RwLock<NatsConnectionState>>,
+    /// Messages published while reconnecting, flushed once connected again
+    pub(crate) pending: Arc<RwLock<PendingBuffer>>,
+    /// Builds the commands to replay once reconnected
+    pub(crate) reconnect_hook: SharedReconnectHook,
+    /// Delays and attempts of the reconnection
+    pub(crate) reconnect_policy: ReconnectPolicy,
+    /// Task polling the `Stream` half, woken up once reconnected
+    pub(crate) read_task: Arc<AtomicTask>,
+    /// Task polling the `Sink` half, woken up once reconnected
+    pub(crate) write_task: Arc<AtomicTask>,
+    /// Lifecycle events of the connection
+    pub(crate) events: Arc<EventBroadcaster>,
+    /// Set when the server stopped answering our PINGs, the connection having to be re-established
+    pub(crate) stale: Arc<AtomicBool>,
 }
 
 impl NatsConnection {
-    /// Tries to reconnect once to the server; Only used internally. Blocks polling during reconnecting
-    /// by forcing the object to return `Async::NotReady`/`AsyncSink::NotReady`
-    fn reconnect(&self) -> impl Future<Item = (), Error = NatsError> {
-        *self.state.write() = NatsConnectionState::Reconnecting;
+    pub(crate) fn new(
+        inner: NatsConnectionInner,
+        pool: ServerPool,
+        tls: Option<TlsConfig>,
+        reconnect_buffer_size: usize,
+        reconnect_policy: ReconnectPolicy,
+        events: Arc<EventBroadcaster>,
+    ) -> Self {
+        NatsConnection {
+            pool: Arc::new(RwLock::new(pool)),
+            tls,
+            inner: Arc::new(RwLock::new(inner)),
+            state: Arc::new(RwLock::new(NatsConnectionState::Connected)),
+            pending: Arc::new(RwLock::new(PendingBuffer::new(reconnect_buffer_size))),
+            reconnect_hook: Arc::new(RwLock::new(None)),
+            reconnect_policy,
+            read_task: Arc::new(AtomicTask::new()),
+            write_task: Arc::new(AtomicTask::new()),
+            events,
+            stale: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns a handle on the shared state of the connection
+    pub(crate) fn handle(&self) -> NatsConnectionHandle {
+        NatsConnectionHandle {
+            pool: Arc::clone(&self.pool),
+            state: Arc::clone(&self.state),
+            pending: Arc::clone(&self.pending),
+            reconnect_hook: Arc::clone(&self.reconnect_hook),
+            stale: Arc::clone(&self.stale),
+            read_task: Arc::clone(&self.read_task),
+            write_task: Arc::clone(&self.write_task),
+        }
+    }
+
+    /// Checks whether the connection is usable, registering `task` to be woken up once reconnected if it isn't.
+    /// Fails with `NatsError::CannotReconnectToServer` if we gave up reconnecting, or with
+    /// `NatsError::ConnectionClosed` if the client closed the connection
+    fn poll_connected(&self, task: &AtomicTask) -> Result<bool, NatsError> {
+        match *self.state.read() {
+            NatsConnectionState::Connected => return Ok(true),
+            NatsConnectionState::Disconnected => return Err(NatsError::CannotReconnectToServer),
+            NatsConnectionState::Closed => return Err(NatsError::ConnectionClosed),
+            NatsConnectionState::Reconnecting => task.register(),
+        }
+
+        // The reconnection might have completed before the task got registered
+        match *self.state.read() {
+            NatsConnectionState::Connected => Ok(true),
+            NatsConnectionState::Disconnected => Err(NatsError::CannotReconnectToServer),
+            NatsConnectionState::Closed => Err(NatsError::ConnectionClosed),
+            NatsConnectionState::Reconnecting => Ok(false),
+        }
+    }
+
+    /// Switches to the `Reconnecting` state; Returns `false` if we weren't connected, meaning that a reconnection
+    /// is already underway
+    fn start_reconnecting(&self) -> bool {
+        let mut state = self.state.write();
+        if *state != NatsConnectionState::Connected {
+            return false;
+        }
+
+        *state = NatsConnectionState::Reconnecting;
+        true
+    }
+
+    /// Keeps an OP that couldn't be sent because we are reconnecting. Messages and subscription changes are
+    /// buffered, as the reconnect hook may have restored the subscriptions before they were made or removed. The
+    /// CONNECT command is replayed by the hook, while PINGs and PONGs are pointless on a new socket
+    fn buffer_op(&self, op: Op) {
+        let dropped = self.pending.write().push(op);
+        match dropped {
+            // `publish` checks for room beforehand, but the buffer may have filled up in the meantime
+            Some(Op::PUB(cmd)) => {
+                debug!(target: "nitox", "Reconnect buffer full, dropping message on {}", cmd.subject);
+                self.events.emit(ClientEvent::ReconnectBufferFull(cmd.subject));
+            }
+            Some(op) => debug!(target: "nitox", "Dropping OP while reconnecting: {:?}", op),
+            None => {}
+        }
+    }
+
+    /// Sends the messages buffered during the reconnection, before anything else
+    fn flush_pending(&self, inner: &mut NatsConnectionInner) -> Poll<(), NatsError> {
+        let mut pending = self.pending.write();
+        while let Some(op) = pending.pop_front() {
+            if let AsyncSink::NotReady(op) = inner.start_send(op)? {
+                pending.push_front(op);
+                return Ok(Async::NotReady);
+            }
+        }
+
+        Ok(Async::Ready(()))
+    }
 
+    /// Tries to reconnect to the server as long as the reconnect policy allows it; Only used internally. Blocks
+    /// polling during reconnecting by forcing the object to return `Async::NotReady`, publishes being buffered
+    /// meanwhile. Once out of attempts, the connection is left `Disconnected` and polling it fails with
+    /// `NatsError::CannotReconnectToServer`
+    fn reconnect(&self) -> impl Future<Item = (), Error = NatsError> {
         let inner_arc = Arc::clone(&self.inner);
         let inner_state = Arc::clone(&self.state);
-        let is_tls = self.is_tls;
-        let maybe_host = self.host.clone();
-        NatsConnectionInner::connect_tcp(&self.addr)
-            .and_then(move |socket| {
-                if is_tls {
-                    Either::A(
-                        // This unwrap is safe because the value would always be present if `is_tls` is true
-                        NatsConnectionInner::upgrade_tcp_to_tls(&maybe_host.unwrap(), socket)
-                            .map(NatsConnectionInner::from),
-                    )
-                } else {
-                    Either::B(future::ok(NatsConnectionInner::from(socket)))
-                }
-            }).and_then(move |inner| {
-                {
-                    *inner_arc.write() = inner;
-                    *inner_state.write() = NatsConnectionState::Connected;
+        let err_state = Arc::clone(&self.state);
+        let hook = Arc::clone(&self.reconnect_hook);
+        let read_task = Arc::clone(&self.read_task);
+        let write_task = Arc::clone(&self.write_task);
+        let err_read_task = Arc::clone(&self.read_task);
+        let err_write_task = Arc::clone(&self.write_task);
+        let loop_state = Arc::clone(&self.state);
+        let policy = self.reconnect_policy.clone();
+        let pool = Arc::clone(&self.pool);
+        let pending = Arc::clone(&self.pending);
+        let tls = self.tls.clone();
+        let events = Arc::clone(&self.events);
+        let err_events = Arc::clone(&self.events);
+
+        future::loop_fn(1, move |attempt| {
+            // The client may close the connection while we are reconnecting
+            if *loop_state.read() == NatsConnectionState::Closed {
+                return Either::A(future::err(NatsError::ConnectionClosed));
+            }
+
+            if !policy.allows_attempt(attempt) {
+                debug!(target: "nitox", "Giving up reconnecting after {} attempts", attempt - 1);
+                return Either::A(future::err(NatsError::CannotReconnectToServer));
+            }
+
+            // Each attempt targets the next server of the pool, so that we fail over to another one
+            let server = pool.write().next_server();
+            let delay = policy.delay(attempt);
+            debug!(target: "nitox", "Reconnection attempt {} to {} in {:?}", attempt, server, delay);
+            events.emit(ClientEvent::Reconnecting(attempt));
+            let events = Arc::clone(&events);
+            let hook = Arc::clone(&hook);
+            let pool = Arc::clone(&pool);
+            let pending = Arc::clone(&pending);
+            let tls = tls.clone();
+            Either::B(
+                Delay::new(Instant::now() + delay)
+                    .from_err()
+                    .and_then(move |_| {
+                        NatsConnection::connect_and_restore(&server, tls, hook, pool, pending)
+                            .map(|inner| (inner, server))
+                    }).then(move |res| match res {
+                        Ok((inner, server)) => {
+                            events.emit(ClientEvent::Connected(server.to_string()));
+                            events.emit(ClientEvent::Reconnected);
+                            Ok(Loop::Break(inner))
+                        }
+                        Err(e) => {
+                            debug!(target: "nitox", "Reconnection attempt {} failed: {}", attempt, e);
+                            Ok(Loop::Continue(attempt + 1))
+                        }
+                    }),
+            )
+        }).and_then(move |inner| {
+            {
+                let mut state = inner_state.write();
+                if *state == NatsConnectionState::Closed {
+                    return Err(NatsError::ConnectionClosed);
                 }
-                debug!(target: "nitox", "Successfully swapped reconnected underlying connection");
-                Ok(())
-            })
+
+                *inner_arc.write() = inner;
+                *state = NatsConnectionState::Connected;
+            }
+            debug!(target: "nitox", "Successfully swapped reconnected underlying connection");
+            read_task.notify();
+            write_task.notify();
+            Ok(())
+        }).map_err(move |e| {
+            if let NatsError::ConnectionClosed = e {
+                return e;
+            }
+
+            *err_state.write() = NatsConnectionState::Disconnected;
+            // Lets the halves report the failure
+            err_read_task.notify();
+            err_write_task.notify();
+            err_events.emit(ClientEvent::Closed);
+            e
+        })
+    }
+
+    /// Makes a single connection attempt. Once the server sent its INFO, the commands built by the reconnect hook
+    /// are sent before the new socket is handed over, so that the session is restored before anything else goes
+    /// through
+    fn connect_and_restore(
+        server: &ServerAddress,
+        tls: Option<TlsConfig>,
+        hook: SharedReconnectHook,
+        pool: Arc<RwLock<ServerPool>>,
+        pending: Arc<RwLock<PendingBuffer>>,
+    ) -> impl Future<Item = NatsConnectionInner, Error = NatsError> {
+        let hook_server = server.clone();
+        NatsConnectionInner::connect(server, tls)
+            .and_then(move |(inner, server_info)| {
+                discover_servers(&pool, &server_info);
+                let ops = match *hook.read() {
+                    Some(ReconnectHook(ref hook)) => hook(&hook_server, &server_info, pending.read().len()),
+                    None => vec![],
+                };
+
+                debug!(target: "nitox", "Replaying {} commands after reconnection", ops.len());
+                inner.send_all(stream::iter_ok::<_, NatsError>(ops))
+            }).map(|(inner, _)| inner)
+    }
+}
+
+/// Adds the servers advertised in an INFO to the pool
+fn discover_servers(pool: &RwLock<ServerPool>, server_info: &ServerInfo) {
+    if let Some(ref connect_urls) = server_info.connect_urls {
+        pool.write().add_discovered(connect_urls);
     }
 }
 
@@ -82,44 +447,63 @@ impl Sink for NatsConnection {
     type SinkItem = Op;
 
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
-        if match self.state.try_read() {
-            Some(state) => *state != NatsConnectionState::Connected,
-            _ => true,
-        } {
-            return Ok(AsyncSink::NotReady(item));
-        }
-
-        if let Some(mut inner) = self.inner.try_write() {
-            match inner.start_send(item.clone()) {
-                Err(NatsError::ServerDisconnected(_)) => {
-                    reco!(self);
-                    Ok(AsyncSink::NotReady(item))
-                }
-                poll_res => poll_res,
+        if !self.poll_connected(&self.write_task)? {
+            self.buffer_op(item);
+            return Ok(AsyncSink::Ready);
+        }
+
+        let mut inner = match self.inner.try_write() {
+            Some(inner) => inner,
+            None => return Ok(AsyncSink::NotReady(item)),
+        };
+
+        // The OP is moved into the socket only once the buffered ones went through, so that it can be buffered in
+        // turn if the connection is lost meanwhile
+        let send_res = match self.flush_pending(&mut inner) {
+            Ok(Async::Ready(_)) => inner.start_send(item),
+            Ok(Async::NotReady) => return Ok(AsyncSink::NotReady(item)),
+            Err(e @ NatsError::ServerDisconnected(_)) => {
+                drop(inner);
+                reco!(self, e.to_string());
+                self.buffer_op(item);
+                return Ok(AsyncSink::Ready);
             }
-        } else {
-            Ok(AsyncSink::NotReady(item))
+            Err(e) => return Err(e),
+        };
+        drop(inner);
+
+        match send_res {
+            // The socket failed while flushing the OPs written before this one, which is lost along with them
+            Err(e @ NatsError::ServerDisconnected(_)) => {
+                reco!(self, e.to_string());
+                Ok(AsyncSink::Ready)
+            }
+            poll_res => poll_res,
         }
     }
 
     fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
-        if match self.state.try_read() {
-            Some(state) => *state != NatsConnectionState::Connected,
-            _ => true,
-        } {
+        // Lets `NatsConnectionHandle::close` wake us up while there is nothing to send
+        self.write_task.register();
+        if !self.poll_connected(&self.write_task)? {
             return Ok(Async::NotReady);
         }
 
-        if let Some(mut inner) = self.inner.try_write() {
-            match inner.poll_complete() {
-                Err(NatsError::ServerDisconnected(_)) => {
-                    reco!(self);
-                    Ok(Async::NotReady)
-                }
-                poll_res => poll_res,
+        let poll_res = if let Some(mut inner) = self.inner.try_write() {
+            match self.flush_pending(&mut inner) {
+                Ok(Async::Ready(_)) => inner.poll_complete(),
+                not_ready_or_err => not_ready_or_err,
             }
         } else {
-            Ok(Async::NotReady)
+            return Ok(Async::NotReady);
+        };
+
+        match poll_res {
+            Err(e @ NatsError::ServerDisconnected(_)) => {
+                reco!(self, e.to_string());
+                Ok(Async::NotReady)
+            }
+            poll_res => poll_res,
         }
     }
 }
@@ -129,23 +513,90 @@ impl Stream for NatsConnection {
     type Item = Op;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        if match self.state.try_read() {
-            Some(state) => *state != NatsConnectionState::Connected,
-            _ => true,
-        } {
+        if !self.poll_connected(&self.read_task)? {
             return Ok(Async::NotReady);
         }
 
-        if let Some(mut inner) = self.inner.try_write() {
-            match inner.poll() {
-                Err(NatsError::ServerDisconnected(_)) => {
-                    reco!(self);
-                    Ok(Async::NotReady)
-                }
-                poll_res => poll_res,
-            }
+        // Lets `NatsConnectionHandle::mark_stale` wake us up while we wait for the socket
+        self.read_task.register();
+        if self.stale.swap(false, Ordering::SeqCst) {
+            reco!(self, "Stale connection, the server stopped answering PINGs".into());
+            // Registers the task, the reconnection being underway
+            self.poll_connected(&self.read_task)?;
+            return Ok(Async::NotReady);
+        }
+
+        let poll_res = if let Some(mut inner) = self.inner.try_write() {
+            inner.poll()
         } else {
-            Ok(Async::NotReady)
+            return Ok(Async::NotReady);
+        };
+
+        match poll_res {
+            // The server closing the socket is a disconnection as well
+            Err(e @ NatsError::ServerDisconnected(_)) => {
+                reco!(self, e.to_string());
+                // Registers the task, the reconnection being underway
+                self.poll_connected(&self.read_task)?;
+                Ok(Async::NotReady)
+            }
+            Ok(Async::Ready(None)) => {
+                reco!(self, "Connection closed by the server".into());
+                // Registers the task, the reconnection being underway
+                self.poll_connected(&self.read_task)?;
+                Ok(Async::NotReady)
+            }
+            // The cluster may advertise new servers at any time
+            Ok(Async::Ready(Some(Op::INFO(server_info)))) => {
+                discover_servers(&self.pool, &server_info);
+                Ok(Async::Ready(Some(Op::INFO(server_info))))
+            }
+            poll_res => poll_res,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PendingBuffer;
+    use protocol::{commands::*, Op};
+
+    fn pub_op(payload: &'static [u8]) -> Op {
+        Op::PUB(PubCommand {
+            subject: "foo".into(),
+            payload: payload.into(),
+            reply_to: None,
+            headers: None,
+        })
+    }
+
+    #[test]
+    fn it_buffers_messages_up_to_capacity() {
+        let mut pending = PendingBuffer::new(8);
+        assert!(pending.push(pub_op(b"12345")).is_none());
+        assert!(pending.push(pub_op(b"6789")).is_some());
+        assert!(pending.push(pub_op(b"678")).is_none());
+        assert!(!pending.has_room_for(1));
+
+        assert_eq!(pending.pop_front(), Some(pub_op(b"12345")));
+        assert!(pending.has_room_for(5));
+        assert_eq!(pending.pop_front(), Some(pub_op(b"678")));
+        assert_eq!(pending.pop_front(), None);
+    }
+
+    #[test]
+    fn it_only_buffers_messages_and_subscription_changes() {
+        let mut pending = PendingBuffer::new(8);
+        assert!(pending.push(pub_op(b"12345678")).is_none());
+        assert!(pending.push(Op::PING).is_some());
+
+        let unsub = Op::UNSUB(UnsubCommand {
+            sid: "1".into(),
+            max_msgs: None,
+        });
+        assert!(pending.push(unsub.clone()).is_none());
+        assert_eq!(pending.pop_front(), Some(pub_op(b"12345678")));
+        assert_eq!(pending.pop_front(), Some(unsub));
+        assert_eq!(pending.pop_front(), None);
+    }
+}
@@ -1,21 +1,27 @@
 use codec::OpCodec;
-use futures::prelude::*;
-use native_tls::TlsConnector as NativeTlsConnector;
-use protocol::Op;
+use futures::{
+    future::{self, Either},
+    prelude::*,
+};
+use protocol::{commands::ServerInfo, Op};
 use std::net::SocketAddr;
 use tokio_codec::{Decoder, Framed};
 use tokio_tcp::TcpStream;
-use tokio_tls::{TlsConnector, TlsStream};
 
 use error::NatsError;
 
+use super::{
+    server_pool::ServerAddress,
+    tls::{self, TlsConfig, TlsStream},
+};
+
 /// Inner raw stream enum over TCP and TLS/TCP
 #[derive(Debug)]
 pub(crate) enum NatsConnectionInner {
     /// Raw TCP Stream framed connection
     Tcp(Box<Framed<TcpStream, OpCodec>>),
     /// TLS over TCP Stream framed connection
-    Tls(Box<Framed<TlsStream<TcpStream>, OpCodec>>),
+    Tls(Box<Framed<TlsStream, OpCodec>>),
 }
 
 impl NatsConnectionInner {
@@ -25,15 +31,38 @@ impl NatsConnectionInner {
         TcpStream::connect(addr).from_err()
     }
 
-    /// Upgrades an existing TCP socket to TLS over TCP
+    /// Upgrades an existing TCP socket to TLS over TCP, verifying the certificate of the server against `host`
+    /// unless the TLS options override it
     pub(crate) fn upgrade_tcp_to_tls(
         host: &str,
+        tls: &TlsConfig,
         socket: TcpStream,
-    ) -> impl Future<Item = TlsStream<TcpStream>, Error = NatsError> {
-        let tls_connector = NativeTlsConnector::builder().build().unwrap();
-        let tls_stream: TlsConnector = tls_connector.into();
-        debug!(target: "nitox", "Connecting to {} through TLS over TCP", host);
-        tls_stream.connect(&host, socket).from_err()
+    ) -> impl Future<Item = TlsStream, Error = NatsError> {
+        tls.connect(host, socket)
+    }
+
+    /// Connects to a server and waits for the INFO it sends as soon as it accepts the connection, then upgrades the
+    /// connection to TLS if either the server or the client requires it. `tls` is `None` when TLS is disabled
+    pub(crate) fn connect(
+        server: &ServerAddress,
+        tls: Option<TlsConfig>,
+    ) -> impl Future<Item = (Self, ServerInfo), Error = NatsError> {
+        let host = server.host.clone();
+        future::result(server.resolve())
+            .and_then(|addr| NatsConnectionInner::connect_tcp(&addr))
+            .and_then(|socket| OpCodec::default().framed(socket).into_future().map_err(|(e, _)| e))
+            .and_then(|(maybe_op, framed)| match maybe_op {
+                Some(Op::INFO(server_info)) => Ok((framed, server_info)),
+                Some(op) => Err(format!("Expected INFO from the server, got {:?}", op).into()),
+                None => Err(NatsError::ServerDisconnected(None)),
+            }).and_then(move |(framed, server_info)| match tls::negotiate(tls.as_ref(), &server_info) {
+                Ok(Some(tls)) => Either::A(
+                    NatsConnectionInner::upgrade_tcp_to_tls(&host, tls, framed.into_inner())
+                        .map(move |socket| (NatsConnectionInner::from(socket), server_info)),
+                ),
+                Ok(None) => Either::B(future::ok((NatsConnectionInner::Tcp(Box::new(framed)), server_info))),
+                Err(e) => Either::B(future::err(e)),
+            })
     }
 }
 
@@ -43,8 +72,8 @@ impl From<TcpStream> for NatsConnectionInner {
     }
 }
 
-impl From<TlsStream<TcpStream>> for NatsConnectionInner {
-    fn from(socket: TlsStream<TcpStream>) -> Self {
+impl From<TlsStream> for NatsConnectionInner {
+    fn from(socket: TlsStream) -> Self {
         NatsConnectionInner::Tls(Box::new(OpCodec::default().framed(socket)))
     }
 }
@@ -1,47 +1,66 @@
-use futures::prelude::*;
-use parking_lot::RwLock;
-use std::net::SocketAddr;
-use std::sync::Arc;
+use futures::{
+    future::{self, Loop},
+    prelude::*,
+};
 
 pub(crate) mod connection;
 mod connection_inner;
+mod reconnect;
+mod server_pool;
+mod tls;
 
 use error::NatsError;
+use events::{ClientEvent, EventBroadcaster};
+use protocol::commands::ServerInfo;
+use std::sync::Arc;
 
-use self::connection::NatsConnectionState;
 use self::connection_inner::*;
 
-pub(crate) use self::connection::NatsConnection;
-
-/// Connect to a raw TCP socket
-pub(crate) fn connect(addr: SocketAddr) -> impl Future<Item = NatsConnection, Error = NatsError> {
-    NatsConnectionInner::connect_tcp(&addr).map(move |socket| {
-        debug!(target: "nitox", "Connected through TCP");
-        NatsConnection {
-            is_tls: false,
-            addr,
-            host: None,
-            state: Arc::new(RwLock::new(NatsConnectionState::Connected)),
-            inner: Arc::new(RwLock::new(socket.into())),
-        }
-    })
-}
+pub(crate) use self::connection::{NatsConnection, NatsConnectionHandle, NatsConnectionState, ReconnectHook};
+pub use self::reconnect::*;
+pub(crate) use self::server_pool::{ServerAddress, ServerPool};
+pub(crate) use self::tls::TlsConfig;
+pub use self::tls::{TlsCertificate, TlsIdentity, TlsOptions};
 
-/// Connect to a TLS over TCP socket. Upgrade is performed automatically
-pub(crate) fn connect_tls(host: String, addr: SocketAddr) -> impl Future<Item = NatsConnection, Error = NatsError> {
-    let inner_host = host.clone();
-    NatsConnectionInner::connect_tcp(&addr)
-        .and_then(move |socket| {
-            debug!(target: "nitox", "Connected through TCP, upgrading to TLS");
-            NatsConnectionInner::upgrade_tcp_to_tls(&host, socket)
-        }).map(move |socket| {
-            debug!(target: "nitox", "Connected through TCP over TLS");
-            NatsConnection {
-                is_tls: true,
-                addr,
-                host: Some(inner_host),
-                state: Arc::new(RwLock::new(NatsConnectionState::Connected)),
-                inner: Arc::new(RwLock::new(socket.into())),
-            }
-        })
+/// Connects to the first reachable server of the pool, upgrading to TLS when required unless `tls` is `None`, and
+/// returns the connection along with the INFO the server greeted us with. Messages published while
+/// reconnecting are buffered up to `reconnect_buffer_size` bytes, reconnections following `reconnect_policy`.
+/// The lifecycle of the connection is reported through `events`
+pub(crate) fn connect(
+    mut pool: ServerPool,
+    tls: Option<TlsConfig>,
+    reconnect_buffer_size: usize,
+    reconnect_policy: ReconnectPolicy,
+    events: Arc<EventBroadcaster>,
+) -> impl Future<Item = (NatsConnection, ServerInfo), Error = NatsError> {
+    let servers_count = pool.len();
+    let first_server = pool.next_server();
+    let connection_tls = tls.clone();
+
+    future::loop_fn((pool, first_server, 1, events), move |(mut pool, server, attempt, events)| {
+        NatsConnectionInner::connect(&server, tls.clone()).then(move |res| match res {
+                Ok((inner, server_info)) => {
+                    debug!(target: "nitox", "Connected to {}", server);
+                    if let Some(ref connect_urls) = server_info.connect_urls {
+                        pool.add_discovered(connect_urls);
+                    }
+
+                    events.emit(ClientEvent::Connected(server.to_string()));
+                    Ok(Loop::Break((pool, inner, server_info, events)))
+                }
+                Err(e) => {
+                    debug!(target: "nitox", "Cannot connect to {}: {}", server, e);
+                    if attempt >= servers_count {
+                        return Err(e);
+                    }
+
+                    let next_server = pool.next_server();
+                    Ok(Loop::Continue((pool, next_server, attempt + 1, events)))
+                }
+            })
+    }).map(move |(pool, inner, server_info, events)| {
+        let connection =
+            NatsConnection::new(inner, pool, connection_tls, reconnect_buffer_size, reconnect_policy, events);
+        (connection, server_info)
+    })
 }
@@ -0,0 +1,158 @@
+use rand::{thread_rng, Rng};
+use std::time::Duration;
+
+/// Governs how the client tries to reconnect to the server once the connection is lost.
+///
+/// The delay before the n-th attempt is `initial_delay * backoff_factor ^ (n - 1)`, capped at `max_delay`, to which
+/// a random delay of up to `jitter` is added so that clients don't all hammer a restarting server at once.
+#[derive(Debug, Clone, PartialEq, Builder)]
+#[builder(default, build_fn(validate = "Self::validate"))]
+pub struct ReconnectPolicy {
+    /// Number of consecutive failed attempts after which we give up with `NatsError::CannotReconnectToServer`.
+    /// Retries forever if `None`
+    pub max_attempts: Option<u32>,
+    /// Delay before the first attempt
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between two attempts, jitter excluded
+    pub max_delay: Duration,
+    /// Factor applied to the delay after each failed attempt
+    pub backoff_factor: f64,
+    /// Maximum random delay added to each attempt
+    pub jitter: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_attempts: Some(60),
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            backoff_factor: 2.0,
+            jitter: Duration::from_millis(100),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn builder() -> ReconnectPolicyBuilder {
+        ReconnectPolicyBuilder::default()
+    }
+
+    /// Whether we are still allowed to make the given attempt, starting from 1
+    pub fn allows_attempt(&self, attempt: u32) -> bool {
+        match self.max_attempts {
+            Some(max_attempts) => attempt <= max_attempts,
+            None => true,
+        }
+    }
+
+    /// Delay to wait for before making the given attempt, starting from 1, jitter excluded
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let initial_ms = duration_as_millis(self.initial_delay) as f64;
+        let max_ms = duration_as_millis(self.max_delay) as f64;
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+
+        Duration::from_millis((initial_ms * self.backoff_factor.powi(exponent)).min(max_ms) as u64)
+    }
+
+    /// Delay to wait for before making the given attempt, starting from 1, jitter included
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let jitter_ms = duration_as_millis(self.jitter);
+        let jitter = if jitter_ms > 0 {
+            Duration::from_millis(thread_rng().gen_range(0, jitter_ms + 1))
+        } else {
+            Duration::default()
+        };
+
+        self.backoff(attempt) + jitter
+    }
+}
+
+impl ReconnectPolicyBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(backoff_factor) = self.backoff_factor {
+            if backoff_factor.is_nan() || backoff_factor < 1.0 {
+                return Err("backoff factor must be greater than or equal to 1".into());
+            }
+        }
+
+        // The delay left unset falls back to its default, which the one set must be consistent with
+        let defaults = ReconnectPolicy::default();
+        let initial_delay = self.initial_delay.unwrap_or(defaults.initial_delay);
+        let max_delay = self.max_delay.unwrap_or(defaults.max_delay);
+        if initial_delay > max_delay {
+            return Err("initial delay cannot exceed the max delay".into());
+        }
+
+        Ok(())
+    }
+}
+
+fn duration_as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReconnectPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn it_backs_off_exponentially() {
+        let policy = ReconnectPolicy::builder()
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(1000))
+            .backoff_factor(2.0)
+            .build()
+            .unwrap();
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(1000), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn it_adds_jitter() {
+        let policy = ReconnectPolicy::builder()
+            .jitter(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        for attempt in 1..10 {
+            let delay = policy.delay(attempt);
+            assert!(delay >= policy.backoff(attempt));
+            assert!(delay <= policy.backoff(attempt) + Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn it_limits_attempts() {
+        let policy = ReconnectPolicy::builder().max_attempts(Some(3)).build().unwrap();
+        assert!(policy.allows_attempt(3));
+        assert!(!policy.allows_attempt(4));
+
+        let policy = ReconnectPolicy::builder().max_attempts(None).build().unwrap();
+        assert!(policy.allows_attempt(u32::MAX));
+    }
+
+    #[test]
+    fn it_rejects_shrinking_backoff() {
+        assert!(ReconnectPolicy::builder().backoff_factor(0.5).build().is_err());
+    }
+
+    #[test]
+    fn it_rejects_initial_delays_exceeding_the_max_delay() {
+        assert!(ReconnectPolicy::builder()
+            .initial_delay(Duration::from_secs(2))
+            .max_delay(Duration::from_secs(1))
+            .build()
+            .is_err());
+        // Compared against the default of the delay left unset
+        assert!(ReconnectPolicy::builder().initial_delay(Duration::from_secs(10)).build().is_err());
+        assert!(ReconnectPolicy::builder().max_delay(Duration::from_millis(10)).build().is_err());
+        assert!(ReconnectPolicy::builder().initial_delay(Duration::from_secs(1)).build().is_ok());
+        assert!(ReconnectPolicy::builder().max_delay(Duration::from_secs(1)).build().is_ok());
+    }
+}
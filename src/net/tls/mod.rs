@@ -0,0 +1,206 @@
+use futures::prelude::*;
+use std::fmt;
+use tokio_tcp::TcpStream;
+
+use error::NatsError;
+use protocol::commands::ServerInfo;
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+mod native;
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+use self::native as backend;
+
+#[cfg(feature = "rustls")]
+mod rustls;
+#[cfg(feature = "rustls")]
+use self::rustls as backend;
+
+pub(crate) use self::backend::TlsStream;
+
+/// Certificate of a trusted certificate authority
+#[derive(Debug, Clone, PartialEq)]
+pub enum TlsCertificate {
+    /// PEM-encoded certificate
+    Pem(Vec<u8>),
+    /// DER-encoded certificate
+    Der(Vec<u8>),
+}
+
+/// Certificate and private key presented to the server when it requires clients to authenticate (mTLS)
+#[derive(Clone, PartialEq)]
+pub enum TlsIdentity {
+    /// DER-encoded PKCS#12 archive, along with its password. Not supported by the `rustls` backend
+    Pkcs12 { der: Vec<u8>, password: String },
+    /// PEM-encoded certificate chain and PKCS#8 private key
+    Pem { cert: Vec<u8>, key: Vec<u8> },
+}
+
+impl fmt::Debug for TlsIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Keeps the private key out of the logs
+        match self {
+            TlsIdentity::Pkcs12 { .. } => f.write_str("TlsIdentity::Pkcs12"),
+            TlsIdentity::Pem { .. } => f.write_str("TlsIdentity::Pem"),
+        }
+    }
+}
+
+/// Settings of the TLS connections to the servers.
+///
+/// Connections are upgraded to TLS once the server sent its INFO, whenever either the server requires it, or the
+/// client does through the `tls_required` flag of its CONNECT command or a `tls://` server URI
+#[derive(Debug, Clone, Default, PartialEq, Builder)]
+#[builder(default)]
+pub struct TlsOptions {
+    /// Certificate authorities to trust on top of the ones of the system, such as a private CA
+    pub root_certificates: Vec<TlsCertificate>,
+    /// Client certificate to present to the server
+    pub identity: Option<TlsIdentity>,
+    /// Name to verify the certificate of the server against, and to send through SNI, instead of the host of the
+    /// server URI. Useful when connecting to servers by IP, and required to do so with the `rustls` backend
+    pub hostname: Option<String>,
+    /// Accepts any certificate, whatever its issuer or hostname. Never enable this outside of tests
+    pub insecure: bool,
+    /// Never upgrades the connections to TLS, failing to connect to the servers requiring it
+    pub disabled: bool,
+}
+
+impl TlsOptions {
+    pub fn builder() -> TlsOptionsBuilder {
+        TlsOptionsBuilder::default()
+    }
+
+    /// Builds the connector applying these options to every connection, or `None` if TLS is disabled. `required`
+    /// tells whether the client requires TLS regardless of what the servers support
+    pub(crate) fn connector(&self, required: bool) -> Result<Option<TlsConfig>, NatsError> {
+        if self.disabled {
+            if required {
+                return Err(NatsError::TlsDisabledError);
+            }
+
+            return Ok(None);
+        }
+
+        Ok(Some(TlsConfig {
+            connector: backend::build_connector(self)?,
+            hostname: self.hostname.clone(),
+            required,
+        }))
+    }
+}
+
+/// TLS connector built from the `TlsOptions`, shared by the initial connection and the reconnections
+#[derive(Clone)]
+pub(crate) struct TlsConfig {
+    pub connector: backend::TlsConnector,
+    pub hostname: Option<String>,
+    /// Whether the client requires TLS, even if the server doesn't
+    pub required: bool,
+}
+
+impl TlsConfig {
+    /// Performs the TLS handshake over `socket`, verifying the certificate of the server against `host` unless the
+    /// options override it
+    pub fn connect(&self, host: &str, socket: TcpStream) -> impl Future<Item = TlsStream, Error = NatsError> {
+        let domain = self.hostname.as_deref().unwrap_or(host);
+        debug!(target: "nitox", "Connecting to {} through TLS over TCP", domain);
+        backend::connect(&self.connector, domain, socket)
+    }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("hostname", &self.hostname)
+            .field("required", &self.required)
+            .finish()
+    }
+}
+
+/// Tells whether the connection to a server must be upgraded to TLS, given the INFO it greeted us with and the
+/// TLS settings of the client, `None` meaning that TLS is disabled. Returns the settings to upgrade with, if so
+pub(crate) fn negotiate<'a>(
+    tls: Option<&'a TlsConfig>,
+    server_info: &ServerInfo,
+) -> Result<Option<&'a TlsConfig>, NatsError> {
+    let server_requires = server_info.tls_required.unwrap_or(false) || server_info.tls_verify.unwrap_or(false);
+    let server_supports = server_requires || server_info.tls_available.unwrap_or(false);
+
+    match tls {
+        None if server_requires => Err(NatsError::TlsRequiredByServerError),
+        Some(tls) if tls.required && !server_supports => Err(NatsError::TlsNotAvailableError),
+        Some(tls) if tls.required || server_requires => Ok(Some(tls)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{negotiate, TlsCertificate, TlsIdentity, TlsOptions};
+    use error::NatsError;
+    use protocol::commands::ServerInfo;
+
+    fn server_info(tls_required: bool, tls_available: bool) -> ServerInfo {
+        ServerInfo::builder()
+            .server_id("test")
+            .version("1.3.0")
+            .go("go1.10.3")
+            .host("0.0.0.0")
+            .port(4222u32)
+            .max_payload(4000u32)
+            .tls_required(Some(tls_required))
+            .tls_available(Some(tls_available))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn it_builds_connectors() {
+        let options = TlsOptions::builder()
+            .root_certificates(vec![TlsCertificate::Pem(
+                include_bytes!("../../../tests/certs/ca.pem").to_vec(),
+            )]).identity(Some(TlsIdentity::Pem {
+                cert: include_bytes!("../../../tests/certs/client-cert.pem").to_vec(),
+                key: include_bytes!("../../../tests/certs/client-key.pem").to_vec(),
+            })).build()
+            .unwrap();
+
+        assert!(options.connector(false).unwrap().is_some());
+    }
+
+    #[test]
+    fn it_rejects_invalid_certificates() {
+        let options = TlsOptions::builder()
+            .root_certificates(vec![TlsCertificate::Pem(b"not a certificate".to_vec())])
+            .build()
+            .unwrap();
+
+        assert!(options.connector(false).is_err());
+    }
+
+    #[test]
+    fn it_negotiates_tls() {
+        let optional = TlsOptions::default().connector(false).unwrap();
+        let required = TlsOptions::default().connector(true).unwrap();
+        let disabled = TlsOptions::builder().disabled(true).build().unwrap().connector(false).unwrap();
+        assert!(disabled.is_none());
+
+        assert!(negotiate(optional.as_ref(), &server_info(false, false)).unwrap().is_none());
+        assert!(negotiate(optional.as_ref(), &server_info(true, false)).unwrap().is_some());
+        assert!(negotiate(required.as_ref(), &server_info(false, true)).unwrap().is_some());
+        assert!(negotiate(disabled.as_ref(), &server_info(false, true)).unwrap().is_none());
+
+        match negotiate(required.as_ref(), &server_info(false, false)) {
+            Err(NatsError::TlsNotAvailableError) => {}
+            res => panic!("Expected TlsNotAvailableError, got {:?}", res),
+        }
+        match negotiate(disabled.as_ref(), &server_info(true, false)) {
+            Err(NatsError::TlsRequiredByServerError) => {}
+            res => panic!("Expected TlsRequiredByServerError, got {:?}", res),
+        }
+        match TlsOptions::builder().disabled(true).build().unwrap().connector(true) {
+            Err(NatsError::TlsDisabledError) => {}
+            res => panic!("Expected TlsDisabledError, got {:?}", res),
+        }
+    }
+}
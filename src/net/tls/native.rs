@@ -0,0 +1,71 @@
+use futures::prelude::*;
+use native_tls::{Certificate, Identity, TlsConnector as NativeTlsConnector};
+use tokio_tcp::TcpStream;
+use tokio_tls;
+
+use error::NatsError;
+
+use super::{TlsCertificate, TlsIdentity, TlsOptions};
+
+/// TLS over TCP stream, backed by the platform's TLS implementation
+pub(crate) type TlsStream = tokio_tls::TlsStream<TcpStream>;
+pub(crate) type TlsConnector = tokio_tls::TlsConnector;
+
+fn certificate(certificate: &TlsCertificate) -> Result<Certificate, NatsError> {
+    Ok(match certificate {
+        TlsCertificate::Pem(pem) => Certificate::from_pem(pem)?,
+        TlsCertificate::Der(der) => Certificate::from_der(der)?,
+    })
+}
+
+fn identity(identity: &TlsIdentity) -> Result<Identity, NatsError> {
+    Ok(match identity {
+        TlsIdentity::Pkcs12 { der, password } => Identity::from_pkcs12(der, password)?,
+        TlsIdentity::Pem { cert, key } => Identity::from_pkcs8(cert, key)?,
+    })
+}
+
+/// Builds a connector trusting the system's root certificates on top of the ones of the options
+pub(crate) fn build_connector(options: &TlsOptions) -> Result<TlsConnector, NatsError> {
+    let mut builder = NativeTlsConnector::builder();
+    for root_certificate in &options.root_certificates {
+        builder.add_root_certificate(certificate(root_certificate)?);
+    }
+
+    if let Some(ref client_identity) = options.identity {
+        builder.identity(identity(client_identity)?);
+    }
+
+    if options.insecure {
+        builder.danger_accept_invalid_certs(true);
+        builder.danger_accept_invalid_hostnames(true);
+    }
+
+    Ok(builder.build()?.into())
+}
+
+pub(crate) fn connect(
+    connector: &TlsConnector,
+    domain: &str,
+    socket: TcpStream,
+) -> impl Future<Item = TlsStream, Error = NatsError> {
+    connector.connect(domain, socket).from_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{TlsIdentity, TlsOptions};
+    use super::build_connector;
+
+    #[test]
+    fn it_loads_pkcs12_identities() {
+        let options = TlsOptions::builder()
+            .identity(Some(TlsIdentity::Pkcs12 {
+                der: include_bytes!("../../../tests/certs/server.p12").to_vec(),
+                password: "nitox".into(),
+            })).build()
+            .unwrap();
+
+        assert!(build_connector(&options).is_ok());
+    }
+}
@@ -0,0 +1,137 @@
+use futures::{
+    future::{self, Either},
+    prelude::*,
+};
+use rustls::{
+    internal::pemfile, sign, Certificate, ClientConfig, PrivateKey, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, TLSError,
+};
+use std::{io, sync::Arc};
+use tokio_rustls::{self, webpki::DNSNameRef};
+use tokio_tcp::TcpStream;
+use webpki_roots::TLS_SERVER_ROOTS;
+
+use error::NatsError;
+
+use super::{TlsCertificate, TlsIdentity, TlsOptions};
+
+/// TLS over TCP stream, backed by rustls
+pub(crate) type TlsStream = tokio_rustls::client::TlsStream<TcpStream>;
+pub(crate) type TlsConnector = tokio_rustls::TlsConnector;
+
+/// Accepts any certificate, for `TlsOptions::insecure`
+struct NoCertificateVerification;
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn certificates(certificate: &TlsCertificate) -> Result<Vec<Certificate>, NatsError> {
+    match certificate {
+        TlsCertificate::Der(der) => Ok(vec![Certificate(der.clone())]),
+        TlsCertificate::Pem(pem) => match pemfile::certs(&mut &pem[..]) {
+            Ok(ref certs) if certs.is_empty() => Err(NatsError::TlsError("No certificate found in PEM".into())),
+            Ok(certs) => Ok(certs),
+            Err(_) => Err(NatsError::TlsError("Invalid PEM certificate".into())),
+        },
+    }
+}
+
+fn private_key(pem: &[u8]) -> Result<PrivateKey, NatsError> {
+    let keys = match pemfile::pkcs8_private_keys(&mut &pem[..]) {
+        Ok(ref keys) if keys.is_empty() => pemfile::rsa_private_keys(&mut &pem[..]),
+        keys => keys,
+    };
+
+    // Makes sure rustls can sign with the key, as it panics otherwise when given to the config
+    match keys.map(|keys| keys.into_iter().next()) {
+        Ok(Some(key)) if sign::any_supported_type(&key).is_ok() => Ok(key),
+        _ => Err(NatsError::TlsError("Invalid or unsupported PEM private key".into())),
+    }
+}
+
+/// Builds a connector trusting the Mozilla root certificates on top of the ones of the options
+pub(crate) fn build_connector(options: &TlsOptions) -> Result<TlsConnector, NatsError> {
+    let mut config = ClientConfig::new();
+    config.root_store.add_server_trust_anchors(&TLS_SERVER_ROOTS);
+    for root_certificate in &options.root_certificates {
+        for certificate in certificates(root_certificate)? {
+            config.root_store.add(&certificate)?;
+        }
+    }
+
+    match options.identity {
+        Some(TlsIdentity::Pem { ref cert, ref key }) => {
+            config.set_single_client_cert(certificates(&TlsCertificate::Pem(cert.clone()))?, private_key(key)?);
+        }
+        Some(TlsIdentity::Pkcs12 { .. }) => {
+            return Err(NatsError::TlsError("PKCS#12 identities are not supported by rustls".into()));
+        }
+        None => {}
+    }
+
+    if options.insecure {
+        config.dangerous().set_certificate_verifier(Arc::new(NoCertificateVerification));
+    }
+
+    Ok(Arc::new(config).into())
+}
+
+/// Handshake failures are reported by tokio-rustls as IO errors wrapping the actual TLS error
+fn handshake_error(e: io::Error) -> NatsError {
+    if e.get_ref().map_or(false, |inner| inner.is::<TLSError>()) {
+        NatsError::TlsError(Box::new(e))
+    } else {
+        NatsError::IOError(e)
+    }
+}
+
+pub(crate) fn connect(
+    connector: &TlsConnector,
+    domain: &str,
+    socket: TcpStream,
+) -> impl Future<Item = TlsStream, Error = NatsError> {
+    // rustls can only verify certificates against DNS names, which doesn't include IPs
+    match DNSNameRef::try_from_ascii_str(domain) {
+        Ok(domain) => Either::A(connector.connect(domain, socket).map_err(handshake_error)),
+        Err(e) => Either::B(future::err(e.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{TlsIdentity, TlsOptions};
+    use super::build_connector;
+
+    #[test]
+    fn it_rejects_pkcs12_identities() {
+        let options = TlsOptions::builder()
+            .identity(Some(TlsIdentity::Pkcs12 {
+                der: include_bytes!("../../../tests/certs/server.p12").to_vec(),
+                password: "nitox".into(),
+            })).build()
+            .unwrap();
+
+        assert!(build_connector(&options).is_err());
+    }
+
+    #[test]
+    fn it_rejects_invalid_private_keys() {
+        let options = TlsOptions::builder()
+            .identity(Some(TlsIdentity::Pem {
+                cert: include_bytes!("../../../tests/certs/client-cert.pem").to_vec(),
+                key: b"not a key".to_vec(),
+            })).build()
+            .unwrap();
+
+        assert!(build_connector(&options).is_err());
+    }
+}
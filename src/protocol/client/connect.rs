@@ -17,13 +17,13 @@ pub struct ConnectCommand {
     pub tls_required: bool,
     /// Client authorization token (if auth_required is set)
     #[serde(skip_serializing_if = "Option::is_none")]
-    auth_token: Option<String>,
+    pub(crate) auth_token: Option<String>,
     /// Connection username (if auth_required is set)
     #[serde(skip_serializing_if = "Option::is_none")]
-    user: Option<String>,
+    pub(crate) user: Option<String>,
     /// Connection password (if auth_required is set)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pass: Option<String>,
+    pub(crate) pass: Option<String>,
     /// Optional client name
     #[serde(skip_serializing_if = "Option::is_none")]
     #[builder(default = "self.default_name()?")]
@@ -44,12 +44,62 @@ pub struct ConnectCommand {
     /// which is when proto in the INFO protocol is set to at least 1.
     #[serde(skip_serializing_if = "Option::is_none")]
     echo: Option<bool>,
+    /// Optional boolean. If set to true, the client supports message headers and the server will deliver
+    /// messages carrying headers with HMSG. Only supported by servers advertising `headers` in their INFO.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) headers: Option<bool>,
+    /// Optional boolean. If set to true along with `headers`, the server replies to requests nobody listens
+    /// to with a `503` status message instead of letting them time out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) no_responders: Option<bool>,
+    /// The public NKey of the client, when authenticating with NKeys
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) nkey: Option<String>,
+    /// The signature of the `nonce` sent by the server in its INFO, made with the NKey of the client and encoded
+    /// in base64url
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) sig: Option<String>,
+    /// The user JWT issued by the operator, when authenticating with a `.creds` file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) jwt: Option<String>,
 }
 
 impl ConnectCommand {
     pub fn builder() -> ConnectCommandBuilder {
         ConnectCommandBuilder::default()
     }
+
+    pub fn auth_token(&self) -> Option<&str> {
+        self.auth_token.as_deref()
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    pub fn pass(&self) -> Option<&str> {
+        self.pass.as_deref()
+    }
+
+    pub fn nkey(&self) -> Option<&str> {
+        self.nkey.as_deref()
+    }
+
+    pub fn sig(&self) -> Option<&str> {
+        self.sig.as_deref()
+    }
+
+    pub fn jwt(&self) -> Option<&str> {
+        self.jwt.as_deref()
+    }
+
+    pub fn headers(&self) -> Option<bool> {
+        self.headers
+    }
+
+    pub fn no_responders(&self) -> Option<bool> {
+        self.no_responders
+    }
 }
 
 impl ConnectCommandBuilder {
@@ -79,7 +129,7 @@ impl Command for ConnectCommand {
     fn try_parse(buf: &[u8]) -> Result<ConnectCommand, CommandError> {
         let len = buf.len();
 
-        if buf[len - 2..] != [b'\r', b'\n'] {
+        if !buf.ends_with(b"\r\n") {
             return Err(CommandError::IncompleteCommandError);
         }
         // Check if we're still on the right command
@@ -1,5 +1,7 @@
-use bytes::{BufMut, Bytes, BytesMut};
-use protocol::{Command, CommandError};
+use bytes::Bytes;
+use protocol::{
+    check_subject, encode_frame, split_frame, split_headers, ArgumentValidationError, Command, CommandError, HeaderMap,
+};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 
 /// The PUB message publishes the message payload to the given subject name, optionally supplying a reply subject.
@@ -17,9 +19,15 @@ pub struct PubCommand {
     /// The message payload data
     #[builder(default, setter(into))]
     pub payload: Bytes,
+    /// Optional message headers; When present, the command is sent as HPUB, which requires a NATS 2.2+ server
+    #[builder(default)]
+    pub headers: Option<HeaderMap>,
 }
 
 impl PubCommand {
+    /// Command name used when the message carries headers
+    pub const HEADERS_CMD_NAME: &'static [u8] = b"HPUB";
+
     pub fn builder() -> PubCommandBuilder {
         PubCommandBuilder::default()
     }
@@ -29,71 +37,69 @@ impl PubCommand {
         let mut rng = thread_rng();
         rng.sample_iter(&Alphanumeric).take(16).collect()
     }
+
+    /// Checks that the subject and the reply inbox are literal subjects that can't inject commands
+    pub(crate) fn check_args(&self) -> Result<(), ArgumentValidationError> {
+        check_subject(&self.subject)?;
+        if let Some(ref reply_to) = self.reply_to {
+            check_subject(reply_to)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Command for PubCommand {
     const CMD_NAME: &'static [u8] = b"PUB";
 
     fn into_vec(self) -> Result<Bytes, CommandError> {
-        let rt = if let Some(reply_to) = self.reply_to {
-            format!("\t{}", reply_to)
-        } else {
-            "".into()
+        self.check_args()?;
+        let args = match self.reply_to {
+            Some(reply_to) => format!("{}\t{}", self.subject, reply_to),
+            None => self.subject,
         };
 
-        let cmd_str = format!("PUB\t{}{}\t{}\r\n", self.subject, rt, self.payload.len());
-        let mut bytes = BytesMut::with_capacity(cmd_str.len() + self.payload.len() + 2);
-        bytes.put(cmd_str.as_bytes());
-        bytes.put(self.payload);
-        bytes.put("\r\n");
-
-        Ok(bytes.freeze())
+        encode_frame(
+            Self::CMD_NAME,
+            Self::HEADERS_CMD_NAME,
+            &args,
+            self.headers.as_ref(),
+            &self.payload,
+        )
     }
 
     fn try_parse(buf: &[u8]) -> Result<Self, CommandError> {
-        let len = buf.len();
-
-        if buf[len - 2..] != [b'\r', b'\n'] {
-            return Err(CommandError::IncompleteCommandError);
-        }
-
-        if let Some(payload_start) = buf[..len - 2].iter().position(|b| *b == b'\r') {
-            if buf[payload_start + 1] != b'\n' {
-                return Err(CommandError::CommandMalformed);
-            }
-
-            let payload: Bytes = buf[payload_start + 2..len - 2].into();
-
-            let whole_command = ::std::str::from_utf8(&buf[..payload_start])?;
-            let mut split = whole_command.split_whitespace();
-            let cmd = split.next().ok_or_else(|| CommandError::CommandMalformed)?;
-            // Check if we're still on the right command
-            if cmd.as_bytes() != Self::CMD_NAME {
-                return Err(CommandError::CommandMalformed);
-            }
+        let (whole_command, body) = split_frame(buf)?;
+        let mut split = whole_command.split_whitespace();
+        let cmd = split.next().ok_or(CommandError::CommandMalformed)?;
+        // Check if we're still on the right command
+        let has_headers = match cmd.as_bytes() {
+            Self::CMD_NAME => false,
+            Self::HEADERS_CMD_NAME => true,
+            _ => return Err(CommandError::CommandMalformed),
+        };
 
-            let payload_len: usize = split
-                .next_back()
-                .ok_or_else(|| CommandError::CommandMalformed)?
-                .parse()?;
+        // Total length, already checked while splitting the frame
+        split.next_back().ok_or(CommandError::CommandMalformed)?;
 
-            if payload.len() != payload_len {
-                return Err(CommandError::CommandMalformed);
-            }
+        let (headers, payload) = if has_headers {
+            let (headers, payload) = split_headers(&mut split, body)?;
+            (Some(headers), payload)
+        } else {
+            (None, body.into())
+        };
 
-            // Extract subject
-            let subject: String = split.next().ok_or_else(|| CommandError::CommandMalformed)?.into();
+        // Extract subject
+        let subject: String = split.next().ok_or(CommandError::CommandMalformed)?.into();
 
-            let reply_to: Option<String> = split.next().map(|v| v.into());
+        let reply_to: Option<String> = split.next().map(|v| v.into());
 
-            Ok(PubCommand {
-                subject,
-                payload,
-                reply_to,
-            })
-        } else {
-            Err(CommandError::CommandMalformed)
-        }
+        Ok(PubCommand {
+            subject,
+            payload,
+            reply_to,
+            headers,
+        })
     }
 }
 
@@ -104,14 +110,25 @@ impl PubCommandBuilder {
         self
     }
 
+    /// Adds a header to the message, turning it into a HPUB command
+    pub fn header<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) -> &mut Self {
+        let mut headers = match self.headers.take() {
+            Some(Some(headers)) => headers,
+            _ => HeaderMap::new(),
+        };
+        headers.append(name, value);
+        self.headers = Some(Some(headers));
+        self
+    }
+
     fn validate(&self) -> Result<(), String> {
         if let Some(ref subj) = self.subject {
-            check_cmd_arg!(subj, "subject");
+            check_cmd_arg!(subj, "subject", check_subject);
         }
 
         if let Some(ref reply_to_maybe) = self.reply_to {
             if let Some(ref reply_to) = reply_to_maybe {
-                check_cmd_arg!(reply_to, "inbox");
+                check_cmd_arg!(reply_to, "inbox", check_subject);
             }
         }
 
@@ -122,7 +139,7 @@ impl PubCommandBuilder {
 #[cfg(test)]
 mod tests {
     use super::{PubCommand, PubCommandBuilder};
-    use protocol::Command;
+    use protocol::{ArgumentValidationError, Command, CommandError};
 
     static DEFAULT_PUB: &'static str = "PUB\tFOO\t11\r\nHello NATS!\r\n";
 
@@ -136,6 +153,26 @@ mod tests {
         assert!(cmd.reply_to.is_none());
     }
 
+    #[test]
+    fn it_parses_binary_payload() {
+        let parse_res = PubCommand::try_parse(b"PUB\tFOO\tBAR\t6\r\n\r\nfoo\r\r\n");
+        assert!(parse_res.is_ok());
+        let cmd = parse_res.unwrap();
+        assert_eq!(&cmd.subject, "FOO");
+        assert_eq!(cmd.reply_to, Some("BAR".into()));
+        assert_eq!(&cmd.payload, &b"\r\nfoo\r"[..]);
+    }
+
+    #[test]
+    fn it_parses_headers() {
+        let parse_res = PubCommand::try_parse(b"HPUB\tFOO\t22\t33\r\nNATS/1.0\r\nBar: baz\r\n\r\nHello NATS!\r\n");
+        assert!(parse_res.is_ok());
+        let cmd = parse_res.unwrap();
+        assert_eq!(&cmd.subject, "FOO");
+        assert_eq!(&cmd.payload, "Hello NATS!");
+        assert_eq!(cmd.headers.unwrap().get("Bar"), Some("baz"));
+    }
+
     #[test]
     fn it_stringifies() {
         let cmd = PubCommandBuilder::default()
@@ -150,4 +187,42 @@ mod tests {
 
         assert_eq!(DEFAULT_PUB, cmd_bytes);
     }
+
+    #[test]
+    fn it_stringifies_headers() {
+        let cmd = PubCommandBuilder::default()
+            .subject("FOO")
+            .payload("Hello NATS!")
+            .header("Bar", "baz")
+            .build()
+            .unwrap();
+
+        let cmd_bytes_res = cmd.into_vec();
+        assert!(cmd_bytes_res.is_ok());
+        let cmd_bytes = cmd_bytes_res.unwrap();
+
+        assert_eq!(
+            "HPUB\tFOO\t22\t33\r\nNATS/1.0\r\nBar: baz\r\n\r\nHello NATS!\r\n",
+            cmd_bytes
+        );
+    }
+
+    #[test]
+    fn it_rejects_injections() {
+        assert!(PubCommandBuilder::default().subject("foo\r\nUNSUB 1").build().is_err());
+        assert!(PubCommandBuilder::default().subject("foo.*").build().is_err());
+        assert!(PubCommandBuilder::default().subject("foo").reply_to(Some("bar.".into())).build().is_err());
+
+        // Commands made without the builder are checked as well
+        let cmd = PubCommand {
+            subject: "foo\r\nUNSUB 1".into(),
+            reply_to: None,
+            payload: "bar".into(),
+            headers: None,
+        };
+        match cmd.into_vec() {
+            Err(CommandError::ValidationError(ArgumentValidationError::ContainsLineBreak)) => {}
+            res => panic!("Expected the subject to be rejected, got {:?}", res),
+        }
+    }
 }
@@ -1,5 +1,5 @@
 use bytes::Bytes;
-use protocol::{Command, CommandError};
+use protocol::{check_command_arg, check_subject_pattern, ArgumentValidationError, Command, CommandError};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 
 /// SUB initiates a subscription to a subject, optionally joining a distributed queue group.
@@ -26,12 +26,23 @@ impl SubCommand {
     pub fn generate_sid() -> String {
         thread_rng().sample_iter(&Alphanumeric).take(12).collect()
     }
+
+    /// Checks that the subject is a well-formed pattern and that none of the arguments can inject commands
+    pub(crate) fn check_args(&self) -> Result<(), ArgumentValidationError> {
+        check_subject_pattern(&self.subject)?;
+        if let Some(ref queue_group) = self.queue_group {
+            check_command_arg(queue_group)?;
+        }
+
+        check_command_arg(&self.sid)
+    }
 }
 
 impl Command for SubCommand {
     const CMD_NAME: &'static [u8] = b"SUB";
 
     fn into_vec(self) -> Result<Bytes, CommandError> {
+        self.check_args()?;
         let qg = if let Some(queue_group) = self.queue_group {
             format!("\t{}", queue_group)
         } else {
@@ -46,7 +57,7 @@ impl Command for SubCommand {
     fn try_parse(buf: &[u8]) -> Result<Self, CommandError> {
         let len = buf.len();
 
-        if buf[len - 2..] != [b'\r', b'\n'] {
+        if !buf.ends_with(b"\r\n") {
             return Err(CommandError::IncompleteCommandError);
         }
 
@@ -76,7 +87,7 @@ impl Command for SubCommand {
 impl SubCommandBuilder {
     fn validate(&self) -> Result<(), String> {
         if let Some(ref subj) = self.subject {
-            check_cmd_arg!(subj, "subject");
+            check_cmd_arg!(subj, "subject", check_subject_pattern);
         }
 
         if let Some(ref qg_maybe) = self.queue_group {
@@ -85,6 +96,10 @@ impl SubCommandBuilder {
             }
         }
 
+        if let Some(ref sid) = self.sid {
+            check_cmd_arg!(sid, "sid");
+        }
+
         Ok(())
     }
 }
@@ -92,7 +107,7 @@ impl SubCommandBuilder {
 #[cfg(test)]
 mod tests {
     use super::{SubCommand, SubCommandBuilder};
-    use protocol::Command;
+    use protocol::{ArgumentValidationError, Command, CommandError};
 
     static DEFAULT_SUB: &'static str = "SUB\tFOO\tpouet\r\n";
 
@@ -119,4 +134,22 @@ mod tests {
 
         assert_eq!(DEFAULT_SUB, cmd_bytes);
     }
+
+    #[test]
+    fn it_checks_wildcards() {
+        assert!(SubCommandBuilder::default().subject("foo.*.>").build().is_ok());
+        assert!(SubCommandBuilder::default().subject("foo.>.bar").build().is_err());
+        assert!(SubCommandBuilder::default().subject("foo*.a>b").build().is_ok());
+        assert!(SubCommandBuilder::default().subject("foo").sid("\0").build().is_err());
+
+        let cmd = SubCommand {
+            subject: "foo.>.bar".into(),
+            queue_group: None,
+            sid: "pouet".into(),
+        };
+        match cmd.into_vec() {
+            Err(CommandError::ValidationError(ArgumentValidationError::MisplacedWildcard)) => {}
+            res => panic!("Expected the subject to be rejected, got {:?}", res),
+        }
+    }
 }
@@ -1,9 +1,10 @@
 use bytes::Bytes;
-use protocol::{commands::SubCommand, Command, CommandError};
+use protocol::{check_command_arg, commands::SubCommand, ArgumentValidationError, Command, CommandError};
 
 /// UNSUB unsubcribes the connection from the specified subject, or auto-unsubscribes after the
 /// specified number of messages has been received.
 #[derive(Debug, Clone, PartialEq, Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct UnsubCommand {
     /// The unique alphanumeric subscription ID of the subject to unsubscribe from
     #[builder(setter(into))]
@@ -17,6 +18,11 @@ impl UnsubCommand {
     pub fn builder() -> UnsubCommandBuilder {
         UnsubCommandBuilder::default()
     }
+
+    /// Checks that the sid can't inject commands
+    pub(crate) fn check_args(&self) -> Result<(), ArgumentValidationError> {
+        check_command_arg(&self.sid)
+    }
 }
 
 impl From<SubCommand> for UnsubCommand {
@@ -32,6 +38,7 @@ impl Command for UnsubCommand {
     const CMD_NAME: &'static [u8] = b"UNSUB";
 
     fn into_vec(self) -> Result<Bytes, CommandError> {
+        self.check_args()?;
         let mm = if let Some(max_msgs) = self.max_msgs {
             format!("\t{}", max_msgs)
         } else {
@@ -44,7 +51,7 @@ impl Command for UnsubCommand {
     fn try_parse(buf: &[u8]) -> Result<Self, CommandError> {
         let len = buf.len();
 
-        if buf[len - 2..] != [b'\r', b'\n'] {
+        if !buf.ends_with(b"\r\n") {
             return Err(CommandError::IncompleteCommandError);
         }
 
@@ -68,6 +75,16 @@ impl Command for UnsubCommand {
     }
 }
 
+impl UnsubCommandBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(ref sid) = self.sid {
+            check_cmd_arg!(sid, "sid");
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{UnsubCommand, UnsubCommandBuilder};
@@ -28,6 +28,9 @@ pub enum CommandError {
     /// Occurs when the payload length exceeds the bounds of integers
     #[fail(display = "PayloadLengthParseError: {}", _0)]
     PayloadLengthParseError(::std::num::ParseIntError),
+    /// Occurs when a message header name or value cannot be represented in a NATS header block
+    #[fail(display = "InvalidHeader: {}", _0)]
+    InvalidHeader(String),
     /// Generic error for untyped `String` errors
     #[fail(display = "GenericError: {}", _0)]
     GenericError(String),
@@ -49,13 +52,32 @@ from_error!(
 from_error!(String, CommandError, CommandError::GenericError);
 
 /// This error is designed to be given when an argument like the `subject` or `queue_group` arguments are
-/// containing spaces or tabs, which is prohibited by the protocol and trigger an error server-side
+/// containing spaces, tabs or line breaks, or a subject is malformed, which is prohibited by the protocol and
+/// triggers an error server-side
 #[derive(Debug, Clone, Eq, PartialEq, Fail)]
 pub enum ArgumentValidationError {
+    /// The argument is empty
+    #[fail(display = "The argument is empty")]
+    Empty,
     /// The argument contains spaces
     #[fail(display = "The argument contains spaces")]
     ContainsSpace,
     /// The argument contains tabs
     #[fail(display = "The argument contains tabs")]
     ContainsTab,
+    /// The argument contains CR or LF characters, which would end the command line
+    #[fail(display = "The argument contains line breaks")]
+    ContainsLineBreak,
+    /// The argument contains NUL characters
+    #[fail(display = "The argument contains NUL characters")]
+    ContainsNul,
+    /// The subject contains an empty token, such as in `foo..bar` or `foo.`
+    #[fail(display = "The subject contains an empty token")]
+    EmptyToken,
+    /// The subject contains wildcards, which are only allowed when subscribing
+    #[fail(display = "The subject contains wildcards")]
+    ContainsWildcard,
+    /// The `>` wildcard isn't the last token of the subject
+    #[fail(display = "The subject contains a misplaced wildcard")]
+    MisplacedWildcard,
 }
@@ -0,0 +1,243 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use protocol::CommandError;
+use std::collections::BTreeMap;
+
+/// Status code sent by the server in reply to a request nobody is listening to
+pub const NO_RESPONDERS_STATUS: u16 = 503;
+
+/// Version line every header block starts with
+const HEADERS_VERSION: &str = "NATS/1.0";
+
+/// Headers attached to a message, sent with HPUB and received with HMSG (NATS 2.2+).
+///
+/// Besides the regular `Name: Value` entries, the server can use the version line of the header block to
+/// convey a status code and its description, for instance `503` when there are no responders to a request.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HeaderMap {
+    /// Optional status code carried by the version line
+    status: Option<u16>,
+    /// Optional description following the status code
+    description: Option<String>,
+    /// Header entries; A name can hold several values
+    entries: BTreeMap<String, Vec<String>>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        HeaderMap::default()
+    }
+
+    /// Status code sent by the server, if any
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    /// Description of the status code sent by the server, if any
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Sets the status code carried by the version line, along with an optional description
+    pub fn set_status(&mut self, status: u16, description: Option<String>) -> &mut Self {
+        self.status = Some(status);
+        self.description = description;
+        self
+    }
+
+    /// Sets the value of a header, replacing any previous one
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) -> &mut Self {
+        self.entries.insert(name.into(), vec![value.into()]);
+        self
+    }
+
+    /// Adds a value to a header, keeping the previous ones
+    pub fn append<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) -> &mut Self {
+        self.entries.entry(name.into()).or_default().push(value.into());
+        self
+    }
+
+    /// Returns the first value of a header
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .get(name)
+            .and_then(|values| values.first())
+            .map(|v| v.as_str())
+    }
+
+    /// Returns all the values of a header
+    pub fn get_all(&self, name: &str) -> &[String] {
+        self.entries.get(name).map(|values| values.as_slice()).unwrap_or(&[])
+    }
+
+    /// Removes a header and returns its values
+    pub fn remove(&mut self, name: &str) -> Option<Vec<String>> {
+        self.entries.remove(name)
+    }
+
+    /// Iterates over every `(name, value)` pair, names being sorted
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .flat_map(|(name, values)| values.iter().map(move |v| (name.as_str(), v.as_str())))
+    }
+
+    /// Number of header names; The status isn't counted, not being a header
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no headers, regardless of the status like `len`
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Encodes the header block, including the trailing empty line
+    pub(crate) fn to_bytes(&self) -> Result<Bytes, CommandError> {
+        let mut block = String::from(HEADERS_VERSION);
+        if let Some(status) = self.status {
+            block.push_str(&format!(" {}", status));
+            if let Some(ref description) = self.description {
+                check_header_value(description)?;
+                block.push(' ');
+                block.push_str(description);
+            }
+        }
+        block.push_str("\r\n");
+
+        for (name, value) in self.iter() {
+            check_header_name(name)?;
+            check_header_value(value)?;
+            block.push_str(name);
+            block.push_str(": ");
+            block.push_str(value);
+            block.push_str("\r\n");
+        }
+
+        block.push_str("\r\n");
+        Ok(block.into())
+    }
+
+    /// Parses a header block, including the trailing empty line
+    pub(crate) fn try_parse(buf: &[u8]) -> Result<Self, CommandError> {
+        let block = ::std::str::from_utf8(buf)?;
+        if !block.ends_with("\r\n\r\n") {
+            return Err(CommandError::CommandMalformed);
+        }
+
+        let mut lines = block[..block.len() - 4].split("\r\n");
+        let version_line = lines.next().ok_or(CommandError::CommandMalformed)?;
+        if !version_line.starts_with(HEADERS_VERSION) {
+            return Err(CommandError::CommandMalformed);
+        }
+
+        let mut headers = HeaderMap::default();
+        let status_line = version_line[HEADERS_VERSION.len()..].trim();
+        if !status_line.is_empty() {
+            let mut split = status_line.splitn(2, ' ');
+            headers.status = Some(split.next().ok_or(CommandError::CommandMalformed)?.parse()?);
+            headers.description = split.next().map(|d| d.trim().into());
+        }
+
+        for line in lines.filter(|l| !l.is_empty()) {
+            let colon = line.find(':').ok_or(CommandError::CommandMalformed)?;
+            headers.append(&line[..colon], line[colon + 1..].trim());
+        }
+
+        Ok(headers)
+    }
+}
+
+/// Encodes a PUB/MSG frame made of the command name, its `args` and the payload length, or its HPUB/HMSG
+/// counterpart when there are headers, where the header block precedes the payload and the lengths are the one of
+/// the header block followed by the total one
+pub(crate) fn encode_frame(
+    cmd_name: &[u8],
+    headers_cmd_name: &[u8],
+    args: &str,
+    headers: Option<&HeaderMap>,
+    payload: &[u8],
+) -> Result<Bytes, CommandError> {
+    let (cmd_name, headers, lengths) = match headers {
+        Some(headers) => {
+            let headers = headers.to_bytes()?;
+            let lengths = format!("{}\t{}", headers.len(), headers.len() + payload.len());
+            (headers_cmd_name, headers, lengths)
+        }
+        None => (cmd_name, Bytes::new(), payload.len().to_string()),
+    };
+
+    let mut bytes =
+        BytesMut::with_capacity(cmd_name.len() + args.len() + lengths.len() + headers.len() + payload.len() + 6);
+    bytes.put(cmd_name);
+    bytes.put_u8(b'\t');
+    bytes.put(args);
+    bytes.put_u8(b'\t');
+    bytes.put(lengths);
+    bytes.put("\r\n");
+    bytes.put(headers);
+    bytes.put(payload);
+    bytes.put("\r\n");
+
+    Ok(bytes.freeze())
+}
+
+fn check_header_name(name: &str) -> Result<(), CommandError> {
+    if name.is_empty() || name.contains(|c: char| c == ':' || c.is_whitespace() || c.is_control()) {
+        return Err(CommandError::InvalidHeader(name.into()));
+    }
+
+    Ok(())
+}
+
+fn check_header_value(value: &str) -> Result<(), CommandError> {
+    if value.contains(&['\r', '\n'][..]) {
+        return Err(CommandError::InvalidHeader(value.into()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderMap;
+
+    static DEFAULT_HEADERS: &str = "NATS/1.0\r\nBar: baz\r\nFoo: 1\r\nFoo: 2\r\n\r\n";
+
+    #[test]
+    fn it_parses() {
+        let parse_res = HeaderMap::try_parse(DEFAULT_HEADERS.as_bytes());
+        assert!(parse_res.is_ok());
+        let headers = parse_res.unwrap();
+        assert!(headers.status().is_none());
+        assert_eq!(headers.get("Bar"), Some("baz"));
+        assert_eq!(headers.get_all("Foo"), &["1".to_string(), "2".to_string()]);
+    }
+
+    #[test]
+    fn it_parses_status() {
+        let parse_res = HeaderMap::try_parse(b"NATS/1.0 503 No Responders\r\n\r\n");
+        assert!(parse_res.is_ok());
+        let headers = parse_res.unwrap();
+        assert_eq!(headers.status(), Some(503));
+        assert_eq!(headers.description(), Some("No Responders"));
+        assert_eq!(headers.len(), 0);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn it_stringifies() {
+        let mut headers = HeaderMap::new();
+        headers.append("Foo", "1").append("Foo", "2").insert("Bar", "baz");
+
+        let bytes_res = headers.to_bytes();
+        assert!(bytes_res.is_ok());
+        assert_eq!(DEFAULT_HEADERS, bytes_res.unwrap());
+    }
+
+    #[test]
+    fn it_rejects_crlf_in_values() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Foo", "bar\r\nUNSUB 1");
+        assert!(headers.to_bytes().is_err());
+    }
+}
@@ -12,8 +12,16 @@ pub trait Command {
         Self: Sized;
 }
 
+/// Checks an argument of a command line, such as a sid or a queue group. Whitespace would split it into several
+/// arguments, and CR, LF or NUL would let it inject commands of its own
 pub(crate) fn check_command_arg(s: &str) -> Result<(), ArgumentValidationError> {
-    if s.contains(' ') {
+    if s.is_empty() {
+        return Err(ArgumentValidationError::Empty);
+    } else if s.contains('\r') || s.contains('\n') {
+        return Err(ArgumentValidationError::ContainsLineBreak);
+    } else if s.contains('\0') {
+        return Err(ArgumentValidationError::ContainsNul);
+    } else if s.contains(' ') {
         return Err(ArgumentValidationError::ContainsSpace);
     } else if s.contains('\t') {
         return Err(ArgumentValidationError::ContainsTab);
@@ -22,18 +30,109 @@ pub(crate) fn check_command_arg(s: &str) -> Result<(), ArgumentValidationError>
     Ok(())
 }
 
+/// Checks a subject messages are published to, which cannot contain wildcards, such as the subject or the reply
+/// inbox of a PUB command. `*` and `>` are only wildcards as whole tokens, being plain characters within a token
+pub(crate) fn check_subject(s: &str) -> Result<(), ArgumentValidationError> {
+    check_command_arg(s)?;
+    for token in s.split('.') {
+        if token.is_empty() {
+            return Err(ArgumentValidationError::EmptyToken);
+        } else if token == "*" || token == ">" {
+            return Err(ArgumentValidationError::ContainsWildcard);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks a subject subscribed to, in which the `*` token matches a single token and the `>` token the remaining
+/// ones, which it must be the last of. Within a token, `*` and `>` are plain characters
+pub(crate) fn check_subject_pattern(s: &str) -> Result<(), ArgumentValidationError> {
+    check_command_arg(s)?;
+    let mut tokens = s.split('.').peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            "" => return Err(ArgumentValidationError::EmptyToken),
+            "*" => {}
+            ">" if tokens.peek().is_none() => {}
+            ">" => return Err(ArgumentValidationError::MisplacedWildcard),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Extracts the payload length announced as the last argument of a PUB/MSG header line (without the trailing CRLF)
+pub(crate) fn parse_payload_len(header: &[u8]) -> Result<usize, CommandError> {
+    let header = ::std::str::from_utf8(header)?;
+    let payload_len = header
+        .split_whitespace()
+        .next_back()
+        .ok_or(CommandError::CommandMalformed)?;
+
+    Ok(payload_len.parse()?)
+}
+
+/// Splits a length-prefixed PUB/HPUB/MSG/HMSG frame into its command line and its payload section, the latter
+/// including the header block if any
+pub(crate) fn split_frame(buf: &[u8]) -> Result<(&str, &[u8]), CommandError> {
+    let len = buf.len();
+
+    if !buf.ends_with(b"\r\n") {
+        return Err(CommandError::IncompleteCommandError);
+    }
+
+    // Arguments cannot contain CR, so the first one we find ends the command line
+    let header_end = buf
+        .iter()
+        .position(|b| *b == b'\r')
+        .ok_or(CommandError::CommandMalformed)?;
+    if buf[header_end + 1] != b'\n' {
+        return Err(CommandError::CommandMalformed);
+    }
+
+    // The payload is length-prefixed and may contain any byte, CRLF included
+    let payload_start = header_end + 2;
+    let frame_end = parse_payload_len(&buf[..header_end])?
+        .checked_add(payload_start + 2)
+        .ok_or(CommandError::CommandMalformed)?;
+    if len < frame_end {
+        return Err(CommandError::IncompleteCommandError);
+    }
+
+    let payload_end = frame_end - 2;
+    if len != frame_end || buf[payload_end..] != [b'\r', b'\n'] {
+        return Err(CommandError::CommandMalformed);
+    }
+
+    Ok((
+        ::std::str::from_utf8(&buf[..header_end])?,
+        &buf[payload_start..payload_end],
+    ))
+}
+
+/// Splits the payload section of a HPUB/HMSG frame into headers and payload, given the remaining arguments of
+/// the command line (the total length having already been consumed)
+pub(crate) fn split_headers<'a, I: DoubleEndedIterator<Item = &'a str>>(
+    args: &mut I,
+    body: &[u8],
+) -> Result<(HeaderMap, Bytes), CommandError> {
+    let headers_len: usize = args.next_back().ok_or(CommandError::CommandMalformed)?.parse()?;
+    if headers_len > body.len() {
+        return Err(CommandError::CommandMalformed);
+    }
+
+    Ok((HeaderMap::try_parse(&body[..headers_len])?, body[headers_len..].into()))
+}
+
 macro_rules! check_cmd_arg {
     ($val:ident, $part:expr) => {
-        use protocol::{check_command_arg, ArgumentValidationError};
-
-        match check_command_arg($val) {
-            Ok(_) => {}
-            Err(ArgumentValidationError::ContainsSpace) => {
-                return Err(format!("{} contains spaces", $part).into());
-            }
-            Err(ArgumentValidationError::ContainsTab) => {
-                return Err(format!("{} contains tabs", $part).into());
-            }
+        check_cmd_arg!($val, $part, check_command_arg);
+    };
+    ($val:ident, $part:expr, $check:ident) => {
+        if let Err(e) = ::protocol::$check($val) {
+            return Err(format!("{} is invalid: {}", $part, e));
         }
     };
 }
@@ -47,17 +146,24 @@ mod server;
 mod op;
 pub use self::op::*;
 
+mod headers;
+pub use self::headers::*;
+
+mod subject;
+pub use self::subject::*;
+
 pub mod commands {
     pub use super::{
         client::{connect::*, pub_cmd::*, sub_cmd::*, unsub_cmd::*},
         server::{info::*, message::*, server_error::ServerError},
+        HeaderMap,
     };
     pub use Command;
 }
 
 #[cfg(test)]
 mod tests {
-    use super::check_command_arg;
+    use super::{check_command_arg, check_subject, check_subject_pattern, ArgumentValidationError};
 
     #[test]
     #[should_panic]
@@ -75,4 +181,36 @@ mod tests {
     fn it_works() {
         check_command_arg(&"foo.bar").unwrap()
     }
+
+    #[test]
+    fn it_detects_injections() {
+        assert_eq!(check_command_arg("foo\r\nUNSUB 1"), Err(ArgumentValidationError::ContainsLineBreak));
+        assert_eq!(check_command_arg("foo\nPING"), Err(ArgumentValidationError::ContainsLineBreak));
+        assert_eq!(check_command_arg("foo\0"), Err(ArgumentValidationError::ContainsNul));
+        assert_eq!(check_command_arg(""), Err(ArgumentValidationError::Empty));
+    }
+
+    #[test]
+    fn it_checks_subjects() {
+        assert!(check_subject("foo.bar").is_ok());
+        assert_eq!(check_subject("foo..bar"), Err(ArgumentValidationError::EmptyToken));
+        assert_eq!(check_subject(".foo"), Err(ArgumentValidationError::EmptyToken));
+        assert_eq!(check_subject("foo."), Err(ArgumentValidationError::EmptyToken));
+        assert_eq!(check_subject("foo.*"), Err(ArgumentValidationError::ContainsWildcard));
+        assert_eq!(check_subject("foo.>"), Err(ArgumentValidationError::ContainsWildcard));
+        assert!(check_subject("foo*bar").is_ok());
+        assert!(check_subject("a>b.*c").is_ok());
+    }
+
+    #[test]
+    fn it_checks_subject_patterns() {
+        assert!(check_subject_pattern("foo.bar").is_ok());
+        assert!(check_subject_pattern("foo.*.bar").is_ok());
+        assert!(check_subject_pattern("*.>").is_ok());
+        assert_eq!(check_subject_pattern("foo.>.bar"), Err(ArgumentValidationError::MisplacedWildcard));
+        assert!(check_subject_pattern("foo*.bar").is_ok());
+        assert!(check_subject_pattern("foo.b>").is_ok());
+        assert!(check_subject_pattern("a>b.*").is_ok());
+        assert_eq!(check_subject_pattern("foo..*"), Err(ArgumentValidationError::EmptyToken));
+    }
 }
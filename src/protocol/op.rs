@@ -1,4 +1,4 @@
-use super::{commands::*, Command, CommandError};
+use super::{commands::*, ArgumentValidationError, Command, CommandError};
 use bytes::Bytes;
 
 /// Abstraction over NATS protocol messages
@@ -8,13 +8,13 @@ pub enum Op {
     INFO(ServerInfo),
     /// **CLIENT** Sent to server to specify connection information
     CONNECT(ConnectCommand),
-    /// **CLIENT** Publish a message to a subject, with optional reply subject
+    /// **CLIENT** Publish a message to a subject, with optional reply subject. Sent as HPUB when it carries headers
     PUB(PubCommand),
     /// **CLIENT** Subscribe to a subject (or subject wildcard)
     SUB(SubCommand),
     /// **CLIENT** Unsubscribe (or auto-unsubscribe) from subject
     UNSUB(UnsubCommand),
-    /// **SERVER** Delivers a message payload to a subscriber
+    /// **SERVER** Delivers a message payload to a subscriber. Received as HMSG when it carries headers
     MSG(Message),
     /// **BOTH** PING keep-alive message
     PING,
@@ -51,17 +51,28 @@ impl Op {
             Op::PING => "PING\r\n".into(),
             Op::PONG => "PONG\r\n".into(),
             Op::OK => "+OK\r\n".into(),
-            Op::ERR(se) => format!("-ERR {}\r\n", se).as_bytes().into(),
+            Op::ERR(se) => format!("-ERR '{}'\r\n", se.message()).as_bytes().into(),
         })
     }
 
+    /// Checks that the arguments of the OP are valid and can't inject commands, as done when encoding it
+    pub(crate) fn check_args(&self) -> Result<(), ArgumentValidationError> {
+        match self {
+            Op::PUB(pc) => pc.check_args(),
+            Op::SUB(sc) => sc.check_args(),
+            Op::UNSUB(uc) => uc.check_args(),
+            Op::MSG(msg) => msg.check_args(),
+            _ => Ok(()),
+        }
+    }
+
     /// Tries to parse from a pair of command name and whole buffer
     pub fn from_bytes(cmd_name: &[u8], buf: &[u8]) -> Result<Self, CommandError> {
         match cmd_name {
             ServerInfo::CMD_NAME => op_from_cmd!(buf, ServerInfo::try_parse, Op::INFO),
             ConnectCommand::CMD_NAME => op_from_cmd!(buf, ConnectCommand::try_parse, Op::CONNECT),
-            Message::CMD_NAME => op_from_cmd!(buf, Message::try_parse, Op::MSG),
-            PubCommand::CMD_NAME => op_from_cmd!(buf, PubCommand::try_parse, Op::PUB),
+            Message::CMD_NAME | Message::HEADERS_CMD_NAME => op_from_cmd!(buf, Message::try_parse, Op::MSG),
+            PubCommand::CMD_NAME | PubCommand::HEADERS_CMD_NAME => op_from_cmd!(buf, PubCommand::try_parse, Op::PUB),
             SubCommand::CMD_NAME => op_from_cmd!(buf, SubCommand::try_parse, Op::SUB),
             UnsubCommand::CMD_NAME => op_from_cmd!(buf, UnsubCommand::try_parse, Op::UNSUB),
             b"PING" => {
@@ -87,7 +98,7 @@ impl Op {
             }
             b"-ERR" => {
                 if &buf[buf.len() - 2..] == b"\r\n" {
-                    Ok(Op::ERR(ServerError::from(String::from_utf8(buf[1..].to_vec())?)))
+                    Ok(Op::ERR(ServerError::parse(&String::from_utf8(buf[4..buf.len() - 2].to_vec())?)))
                 } else {
                     Err(CommandError::IncompleteCommandError)
                 }
@@ -52,10 +52,26 @@ pub struct ServerInfo {
     #[builder(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) tls_verify: Option<bool>,
+    /// If this is set, the server accepts TLS connections without requiring them
+    #[builder(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tls_available: Option<bool>,
     /// An optional list of server urls that a client can connect to.
     #[builder(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) connect_urls: Option<Vec<String>>,
+    /// If this is set, the server supports message headers (HPUB/HMSG), which is the case from version 2.2.0
+    #[builder(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) headers: Option<bool>,
+    /// If this is set, the server is in lame duck mode: it is shutting down and clients should move to another server
+    #[builder(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) ldm: Option<bool>,
+    /// If this is set, the client authenticates with an NKey by signing this nonce in its CONNECT
+    #[builder(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) nonce: Option<String>,
 }
 
 impl ServerInfo {
@@ -74,7 +90,7 @@ impl Command for ServerInfo {
     fn try_parse(buf: &[u8]) -> Result<Self, CommandError> {
         let len = buf.len();
 
-        if buf[len - 2..] != [b'\r', b'\n'] {
+        if !buf.ends_with(b"\r\n") {
             return Err(CommandError::IncompleteCommandError);
         }
         // Check if we're still on the right command
@@ -1,5 +1,8 @@
-use bytes::{BufMut, Bytes, BytesMut};
-use protocol::{Command, CommandError};
+use bytes::Bytes;
+use protocol::{
+    check_command_arg, check_subject, encode_frame, split_frame, split_headers, ArgumentValidationError, Command,
+    CommandError, HeaderMap, NO_RESPONDERS_STATUS,
+};
 
 /// The MSG protocol message is used to deliver an application message to the client.
 #[derive(Debug, Clone, PartialEq, Builder)]
@@ -17,92 +20,108 @@ pub struct Message {
     /// The message payload data
     #[builder(setter(into))]
     pub payload: Bytes,
+    /// Headers of the message, present when it has been delivered through HMSG
+    #[builder(default)]
+    pub headers: Option<HeaderMap>,
 }
 
 impl Message {
+    /// Command name used when the message carries headers
+    pub const HEADERS_CMD_NAME: &'static [u8] = b"HMSG";
+
     pub fn builder() -> MessageBuilder {
         MessageBuilder::default()
     }
+
+    /// Whether this message is the status message sent by the server when a request has no responders
+    pub fn is_no_responders(&self) -> bool {
+        self.payload.is_empty()
+            && self.headers.as_ref().and_then(|h| h.status()) == Some(NO_RESPONDERS_STATUS)
+    }
+
+    /// Checks that the subject and the reply inbox are literal subjects and that none of the arguments can inject
+    /// commands
+    pub(crate) fn check_args(&self) -> Result<(), ArgumentValidationError> {
+        check_subject(&self.subject)?;
+        check_command_arg(&self.sid)?;
+        if let Some(ref reply_to) = self.reply_to {
+            check_subject(reply_to)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Command for Message {
     const CMD_NAME: &'static [u8] = b"MSG";
 
     fn into_vec(self) -> Result<Bytes, CommandError> {
-        let rt = if let Some(reply_to) = self.reply_to {
-            format!("\t{}", reply_to)
-        } else {
-            "".into()
+        self.check_args()?;
+        let args = match self.reply_to {
+            Some(reply_to) => format!("{}\t{}\t{}", self.subject, self.sid, reply_to),
+            None => format!("{}\t{}", self.subject, self.sid),
         };
 
-        let cmd_str = format!("MSG\t{}\t{}{}\t{}\r\n", self.subject, self.sid, rt, self.payload.len());
-        let mut bytes = BytesMut::with_capacity(cmd_str.len() + self.payload.len() + 2);
-        bytes.put(cmd_str.as_bytes());
-        bytes.put(self.payload);
-        bytes.put("\r\n");
-
-        Ok(bytes.freeze())
+        encode_frame(
+            Self::CMD_NAME,
+            Self::HEADERS_CMD_NAME,
+            &args,
+            self.headers.as_ref(),
+            &self.payload,
+        )
     }
 
     fn try_parse(buf: &[u8]) -> Result<Self, CommandError> {
-        let len = buf.len();
-
-        if buf[len - 2..] != [b'\r', b'\n'] {
-            return Err(CommandError::IncompleteCommandError);
-        }
-
-        if let Some(payload_start) = buf[..len - 2].iter().position(|b| *b == b'\r') {
-            if buf[payload_start + 1] != b'\n' {
-                return Err(CommandError::CommandMalformed);
-            }
-
-            let payload: Bytes = buf[payload_start + 2..len - 2].into();
-
-            let whole_command = ::std::str::from_utf8(&buf[..payload_start])?;
-            let mut split = whole_command.split_whitespace();
-            let cmd = split.next().ok_or_else(|| CommandError::CommandMalformed)?;
-            // Check if we're still on the right command
-            if cmd.as_bytes() != Self::CMD_NAME {
-                return Err(CommandError::CommandMalformed);
-            }
+        let (whole_command, body) = split_frame(buf)?;
+        let mut split = whole_command.split_whitespace();
+        let cmd = split.next().ok_or(CommandError::CommandMalformed)?;
+        // Check if we're still on the right command
+        let has_headers = match cmd.as_bytes() {
+            Self::CMD_NAME => false,
+            Self::HEADERS_CMD_NAME => true,
+            _ => return Err(CommandError::CommandMalformed),
+        };
 
-            let payload_len: usize = split
-                .next_back()
-                .ok_or_else(|| CommandError::CommandMalformed)?
-                .parse()?;
+        // Total length, already checked while splitting the frame
+        split.next_back().ok_or(CommandError::CommandMalformed)?;
 
-            if payload.len() != payload_len {
-                return Err(CommandError::CommandMalformed);
-            }
+        let (headers, payload) = if has_headers {
+            let (headers, payload) = split_headers(&mut split, body)?;
+            (Some(headers), payload)
+        } else {
+            (None, body.into())
+        };
 
-            // Extract subject
-            let subject: String = split.next().ok_or_else(|| CommandError::CommandMalformed)?.into();
+        // Extract subject
+        let subject: String = split.next().ok_or(CommandError::CommandMalformed)?.into();
 
-            let sid: String = split.next().ok_or_else(|| CommandError::CommandMalformed)?.into();
+        let sid: String = split.next().ok_or(CommandError::CommandMalformed)?.into();
 
-            let reply_to: Option<String> = split.next().map(|v| v.into());
+        let reply_to: Option<String> = split.next().map(|v| v.into());
 
-            Ok(Message {
-                subject,
-                sid,
-                payload,
-                reply_to,
-            })
-        } else {
-            Err(CommandError::CommandMalformed)
-        }
+        Ok(Message {
+            subject,
+            sid,
+            payload,
+            reply_to,
+            headers,
+        })
     }
 }
 
 impl MessageBuilder {
     fn validate(&self) -> Result<(), String> {
         if let Some(ref subj) = self.subject {
-            check_cmd_arg!(subj, "subject");
+            check_cmd_arg!(subj, "subject", check_subject);
+        }
+
+        if let Some(ref sid) = self.sid {
+            check_cmd_arg!(sid, "sid");
         }
 
         if let Some(ref reply_to_maybe) = self.reply_to {
             if let Some(ref reply_to) = reply_to_maybe {
-                check_cmd_arg!(reply_to, "inbox");
+                check_cmd_arg!(reply_to, "inbox", check_subject);
             }
         }
 
@@ -128,6 +147,30 @@ mod tests {
         assert_eq!(cmd.payload, "toto");
     }
 
+    #[test]
+    fn it_parses_binary_payload() {
+        let parse_res = Message::try_parse(b"MSG\tFOO\tpouet\t6\r\nto\r\nto\r\n");
+        assert!(parse_res.is_ok());
+        let cmd = parse_res.unwrap();
+        assert_eq!(&cmd.sid, "pouet");
+        assert_eq!(cmd.payload, "to\r\nto");
+    }
+
+    #[test]
+    fn it_parses_headers() {
+        let parse_res = Message::try_parse(b"HMSG\tFOO\tpouet\tBAR\t16\t20\r\nNATS/1.0 503\r\n\r\ntoto\r\n");
+        assert!(parse_res.is_ok());
+        let cmd = parse_res.unwrap();
+        assert_eq!(cmd.reply_to, Some("BAR".into()));
+        assert_eq!(cmd.payload, "toto");
+        assert_eq!(cmd.headers.unwrap().status(), Some(503));
+    }
+
+    #[test]
+    fn it_rejects_payload_length_mismatch() {
+        assert!(Message::try_parse(b"MSG\tFOO\tpouet\t2\r\ntoto\r\n").is_err());
+    }
+
     #[test]
     fn it_stringifies() {
         let cmd = MessageBuilder::default()
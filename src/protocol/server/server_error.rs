@@ -12,6 +12,29 @@ impl From<String> for ServerError {
     }
 }
 
+impl ServerError {
+    /// Parses the message following `-ERR`, which the server wraps in quotes
+    pub(crate) fn parse(message: &str) -> Self {
+        ServerError(message.trim().trim_matches('\'').into())
+    }
+
+    pub fn message(&self) -> &str {
+        &self.0
+    }
+
+    /// Indicates if the server rejected the credentials sent in the CONNECT command
+    pub fn is_authorization_violation(&self) -> bool {
+        self.message().eq_ignore_ascii_case("Authorization Violation")
+    }
+
+    /// Indicates if the server closes the connection after this error. Invalid subjects and permission violations
+    /// only reject the command that caused them
+    pub fn closes_connection(&self) -> bool {
+        let message = self.message().to_ascii_lowercase();
+        !message.starts_with("invalid") && !message.starts_with("permissions violation")
+    }
+}
+
 impl fmt::Display for ServerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_tuple("ServerError").field(&self.0).finish()
@@ -0,0 +1,221 @@
+use std::{fmt, str::FromStr};
+
+use protocol::{check_subject, check_subject_pattern, ArgumentValidationError};
+
+/// Separator of the tokens of a subject
+const TOKEN_SEPARATOR: char = '.';
+/// Wildcard matching a single token
+const SINGLE_WILDCARD: &str = "*";
+/// Wildcard matching the remaining tokens, at least one
+const FULL_WILDCARD: &str = ">";
+
+/// Literal subject messages are published to, such as `foo.bar`. It is guaranteed to be well-formed: made of
+/// non-empty tokens separated by dots, without wildcards, whitespace or line breaks
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Subject(String);
+
+impl Subject {
+    /// Validates a subject
+    pub fn new<S: Into<String>>(subject: S) -> Result<Self, ArgumentValidationError> {
+        let subject = subject.into();
+        check_subject(&subject)?;
+        Ok(Subject(subject))
+    }
+
+    /// Builds a subject by joining `tokens` with dots
+    pub fn from_tokens<I, S>(tokens: I) -> Result<Self, ArgumentValidationError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Subject::new(join_tokens(tokens))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Iterates over the tokens of the subject
+    pub fn tokens(&self) -> impl Iterator<Item = &str> {
+        self.0.split(TOKEN_SEPARATOR)
+    }
+}
+
+/// Subject subscribed to, in which `*` matches any single token and a trailing `>` any number of remaining tokens,
+/// such as `foo.*.baz` or `foo.>`. It is guaranteed to be well-formed like `Subject`, with wildcards as whole tokens
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubjectPattern(String);
+
+impl SubjectPattern {
+    /// Validates a subject pattern
+    pub fn new<S: Into<String>>(pattern: S) -> Result<Self, ArgumentValidationError> {
+        let pattern = pattern.into();
+        check_subject_pattern(&pattern)?;
+        Ok(SubjectPattern(pattern))
+    }
+
+    /// Builds a pattern by joining `tokens` with dots
+    pub fn from_tokens<I, S>(tokens: I) -> Result<Self, ArgumentValidationError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        SubjectPattern::new(join_tokens(tokens))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Iterates over the tokens of the pattern, wildcards included
+    pub fn tokens(&self) -> impl Iterator<Item = &str> {
+        self.0.split(TOKEN_SEPARATOR)
+    }
+
+    /// Whether the pattern contains no wildcards, only matching the subject it is made of
+    pub fn is_literal(&self) -> bool {
+        self.tokens().all(|token| token != SINGLE_WILDCARD && token != FULL_WILDCARD)
+    }
+
+    /// Whether a message published on `subject` is delivered to a subscription on this pattern
+    pub fn matches<S: AsRef<str>>(&self, subject: S) -> bool {
+        let mut subject_tokens = subject.as_ref().split(TOKEN_SEPARATOR);
+        for token in self.tokens() {
+            match (token, subject_tokens.next()) {
+                // `>` being the last token, it matches whatever follows
+                (FULL_WILDCARD, Some(subject_token)) => return !subject_token.is_empty(),
+                (_, None) => return false,
+                (_, Some("")) => return false,
+                (SINGLE_WILDCARD, Some(_)) => {}
+                (token, Some(subject_token)) if token == subject_token => {}
+                _ => return false,
+            }
+        }
+
+        subject_tokens.next().is_none()
+    }
+}
+
+fn join_tokens<I, S>(tokens: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    tokens.into_iter().fold(String::new(), |mut joined, token| {
+        if !joined.is_empty() {
+            joined.push(TOKEN_SEPARATOR);
+        }
+        joined.push_str(token.as_ref());
+        joined
+    })
+}
+
+macro_rules! subject_conversions {
+    ($type:ident) => {
+        impl FromStr for $type {
+            type Err = ArgumentValidationError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $type::new(s)
+            }
+        }
+
+        impl AsRef<str> for $type {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $type {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl From<$type> for String {
+            fn from(subject: $type) -> Self {
+                subject.0
+            }
+        }
+    };
+}
+
+subject_conversions!(Subject);
+subject_conversions!(SubjectPattern);
+
+impl From<Subject> for SubjectPattern {
+    fn from(subject: Subject) -> Self {
+        SubjectPattern(subject.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Subject, SubjectPattern};
+    use protocol::{commands::*, ArgumentValidationError};
+
+    fn pattern(s: &str) -> SubjectPattern {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn it_validates_subjects() {
+        assert!(Subject::new("foo.bar").is_ok());
+        assert_eq!(Subject::new("foo.*"), Err(ArgumentValidationError::ContainsWildcard));
+        assert!(Subject::new("foo*bar.a>b").is_ok());
+        assert_eq!(Subject::new("foo\r\n"), Err(ArgumentValidationError::ContainsLineBreak));
+        assert!(SubjectPattern::new("foo.*.>").is_ok());
+        assert_eq!(SubjectPattern::new("foo.>.bar"), Err(ArgumentValidationError::MisplacedWildcard));
+    }
+
+    #[test]
+    fn it_matches_subjects() {
+        assert!(pattern("foo.bar").matches("foo.bar"));
+        assert!(!pattern("foo.bar").matches("foo.baz"));
+        assert!(!pattern("foo.bar").matches("foo.bar.baz"));
+        assert!(!pattern("foo.bar").matches("foo"));
+
+        assert!(pattern("foo.*.baz").matches("foo.bar.baz"));
+        assert!(!pattern("foo.*.baz").matches("foo.baz"));
+        assert!(!pattern("foo.*").matches("foo.bar.baz"));
+
+        assert!(pattern("foo.>").matches("foo.bar"));
+        assert!(pattern("foo.>").matches("foo.bar.baz"));
+        assert!(!pattern("foo.>").matches("foo"));
+        assert!(pattern(">").matches("foo"));
+        assert!(pattern("*.>").matches("foo.bar"));
+        assert!(!pattern("*.>").matches("foo"));
+
+        assert!(!pattern("foo.*").matches("foo."));
+        assert!(!pattern("foo.>").matches("foo."));
+
+        // Only whole tokens are wildcards
+        assert!(pattern("foo*.a>b").is_literal());
+        assert!(pattern("foo*.a>b").matches("foo*.a>b"));
+        assert!(!pattern("foo*").matches("foobar"));
+    }
+
+    #[test]
+    fn it_iterates_over_tokens() {
+        let subject = Subject::from_tokens(["foo", "bar"]).unwrap();
+        assert_eq!(subject.as_str(), "foo.bar");
+        assert_eq!(subject.tokens().collect::<Vec<_>>(), vec!["foo", "bar"]);
+        assert!(Subject::from_tokens(["foo", ""]).is_err());
+
+        let pattern = SubjectPattern::from_tokens(vec!["foo", "*"]).unwrap();
+        assert_eq!(pattern.tokens().last(), Some("*"));
+        assert!(!pattern.is_literal());
+        assert!(SubjectPattern::from(subject).is_literal());
+    }
+
+    #[test]
+    fn it_builds_commands() {
+        let cmd = SubCommand::builder().subject(pattern("foo.*")).build().unwrap();
+        assert_eq!(cmd.subject, "foo.*");
+
+        let subject = Subject::new("foo.bar").unwrap();
+        let cmd = PubCommand::builder().subject(subject.clone()).build().unwrap();
+        assert!(pattern("foo.*").matches(&cmd.subject));
+        assert!(pattern("foo.*").matches(&subject));
+    }
+}